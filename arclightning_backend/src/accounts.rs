@@ -0,0 +1,249 @@
+// Player accounts for the web app.
+//
+// Accounts live in their own TOML file (accounts_path in the config), so the
+// cabinet config never holds player data, and only its owner can read it.
+// Usernames are unique ignoring case but keep the case they were registered
+// with. Passwords are hashed by the password module with the policy in
+// [auth], and weaker hashes are replaced when their player signs in.
+
+use config::write_private;
+use password::{self, HashPolicy, Verified};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use toml;
+
+pub const MIN_USERNAME_LEN: usize = 3;
+pub const MAX_USERNAME_LEN: usize = 32;
+pub const MIN_PASSWORD_LEN: usize = 8;
+// bcrypt ignores anything past 72 bytes
+pub const MAX_PASSWORD_LEN: usize = 72;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Account {
+    // As registered; lookups ignore case
+    pub username: String,
+    pub password_hash: String,
+    pub created: u64,
+}
+
+// The layout of the accounts file
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct AccountsFile {
+    // Keyed by lowercased username
+    #[serde(default)]
+    accounts: BTreeMap<String, Account>,
+}
+
+// Letters, digits, '_', '-' and '.', starting with a letter or digit
+pub fn validate_username(username: &str) -> Result<(), String> {
+    let len = username.chars().count();
+    if !(MIN_USERNAME_LEN..=MAX_USERNAME_LEN).contains(&len) {
+        return Err(format!(
+            "Usernames must be {} to {} characters long",
+            MIN_USERNAME_LEN, MAX_USERNAME_LEN
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+    {
+        return Err("Usernames can only contain letters, digits, '_', '-' and '.'".to_owned());
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err("Usernames must start with a letter or digit".to_owned());
+    }
+    Ok(())
+}
+
+pub fn validate_password(password: &str, username: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!(
+            "Passwords must be at least {} characters long",
+            MIN_PASSWORD_LEN
+        ));
+    }
+    if password.len() > MAX_PASSWORD_LEN {
+        return Err(format!(
+            "Passwords can be at most {} bytes long",
+            MAX_PASSWORD_LEN
+        ));
+    }
+    if password.eq_ignore_ascii_case(username) {
+        return Err("The password can't be the username".to_owned());
+    }
+    Ok(())
+}
+
+fn key(username: &str) -> String {
+    username.to_lowercase()
+}
+
+// Shared by every clone; every change is written to the file right away
+#[derive(Debug, Clone)]
+pub struct Accounts {
+    path: PathBuf,
+    accounts: Arc<Mutex<BTreeMap<String, Account>>>,
+    // Checked in place of a missing account's hash, made with the policy
+    // it's kept next to
+    dummy_hash: Arc<Mutex<Option<(HashPolicy, String)>>>,
+}
+
+impl Accounts {
+    // Reads the accounts in `path`. A missing file has no accounts yet.
+    pub fn load(path: PathBuf) -> Result<Self, io::Error> {
+        let mut source = String::new();
+        match File::open(&path) {
+            Ok(mut file) => {
+                file.read_to_string(&mut source)?;
+            }
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let file: AccountsFile = toml::from_str(&source).map_err(|err| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse {:?}: {}", path, err),
+            )
+        })?;
+
+        Ok(Accounts {
+            path,
+            accounts: Arc::new(Mutex::new(file.accounts)),
+            dummy_hash: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<BTreeMap<String, Account>>, io::Error> {
+        self.accounts.lock().map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on accounts: {}", err),
+            )
+        })
+    }
+
+    // A hash of no one's password, so signing in to a missing account takes
+    // as long as signing in with a wrong password
+    fn dummy_hash(&self, policy: &HashPolicy) -> Result<String, io::Error> {
+        let mut dummy_hash = self.dummy_hash.lock().map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on dummy hash: {}", err),
+            )
+        })?;
+        match *dummy_hash {
+            Some((ref made_with, ref hash)) if made_with == policy => return Ok(hash.clone()),
+            _ => {}
+        }
+        let hash = password::hash("not anyone's password", policy)?;
+        *dummy_hash = Some((*policy, hash.clone()));
+        Ok(hash)
+    }
+
+    fn save(&self, accounts: &BTreeMap<String, Account>) -> Result<(), io::Error> {
+        let file = AccountsFile {
+            accounts: accounts.clone(),
+        };
+        let source = toml::to_string(&file).map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to serialize accounts: {}", err),
+            )
+        })?;
+        write_private(&self.path, &source)
+    }
+
+    pub fn get(&self, username: &str) -> Result<Option<Account>, io::Error> {
+        Ok(self.lock()?.get(&key(username)).cloned())
+    }
+
+    // Creates an account. Fails with InvalidInput if the username or
+    // password breaks the rules, and AlreadyExists if the username is
+    // taken.
+    pub fn register(
+        &self,
+        username: &str,
+        password: &str,
+        policy: &HashPolicy,
+        now: u64,
+    ) -> Result<Account, io::Error> {
+        validate_username(username)
+            .and_then(|_| validate_password(password, username))
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let taken = || {
+            io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("The username {:?} is taken", username),
+            )
+        };
+        // Hashing is slow, so don't hold the lock for it
+        if self.get(username)?.is_some() {
+            return Err(taken());
+        }
+        let account = Account {
+            username: username.to_owned(),
+            password_hash: password::hash(password, policy)?,
+            created: now,
+        };
+
+        let mut accounts = self.lock()?;
+        if accounts.contains_key(&key(username)) {
+            return Err(taken());
+        }
+        accounts.insert(key(username), account.clone());
+        if let Err(err) = self.save(&accounts) {
+            accounts.remove(&key(username));
+            return Err(err);
+        }
+        Ok(account)
+    }
+
+    // The account `username` if `password` is its password. A hash weaker
+    // than `policy` is replaced.
+    pub fn sign_in(
+        &self,
+        username: &str,
+        password: &str,
+        policy: &HashPolicy,
+    ) -> Result<Option<Account>, io::Error> {
+        let account = match self.get(username)? {
+            Some(account) => account,
+            None => {
+                // Don't give away which usernames exist by answering sooner
+                password::verify(password, &self.dummy_hash(policy)?)?;
+                return Ok(None);
+            }
+        };
+
+        match password::check_password(password, &account.password_hash, policy)? {
+            Verified::Wrong => Ok(None),
+            Verified::Correct => Ok(Some(account)),
+            Verified::Rehashed(new_hash) => {
+                let mut accounts = self.lock()?;
+                // Unless the password was changed in the meantime
+                let unchanged = accounts.get(&key(username)).map_or(false, |stored| {
+                    stored.password_hash == account.password_hash
+                });
+                if unchanged {
+                    let mut updated = account.clone();
+                    updated.password_hash = new_hash;
+                    accounts.insert(key(username), updated.clone());
+                    match self.save(&accounts) {
+                        Ok(()) => return Ok(Some(updated)),
+                        Err(err) => {
+                            println!(
+                                "Failed to store the rehashed password of {}: {}",
+                                username, err
+                            );
+                            accounts.insert(key(username), account.clone());
+                        }
+                    }
+                }
+                Ok(Some(account))
+            }
+        }
+    }
+}
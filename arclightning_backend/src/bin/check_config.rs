@@ -0,0 +1,39 @@
+/*
+ * Checks a server config for problems before it is deployed.
+ *
+ * Usage: check_config [path/to/server_config.toml]
+ *
+ * Every problem is printed as file:line:column: message, and the exit
+ * code is non-zero if any were found.
+ */
+
+extern crate arclightning_backend;
+
+use arclightning_backend::check::check_file;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    // Note: Without an argument this requires running from the project root directory
+    let toml_filepath: PathBuf = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ["server_config.toml"].iter().collect());
+
+    let diagnostics = check_file(&toml_filepath);
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+
+    if diagnostics.is_empty() {
+        println!("{}: ok", toml_filepath.display());
+    } else {
+        eprintln!(
+            "{}: {} problem(s) found",
+            toml_filepath.display(),
+            diagnostics.len()
+        );
+        process::exit(1);
+    }
+}
@@ -0,0 +1,103 @@
+/*
+ * Moves games between the server config and other front-ends.
+ *
+ * Usage: games_xml [--config path] [--format emulationstation|launchbox] [--dry-run] import <file.xml>
+ *        games_xml [--config path] --format emulationstation|launchbox export <file.xml>
+ *
+ * import reads an EmulationStation gamelist.xml or a LaunchBox platform
+ * file (LaunchBox/Data/Platforms/<platform>.xml) and adds its games to the
+ * config; with --dry-run they are only listed. export writes every game in
+ * the config to a new file. Either way, anything that has no equivalent on
+ * the other side is listed at the end.
+ */
+
+extern crate arclightning_backend;
+
+use arclightning_backend::config::{write_atomically, Config};
+use arclightning_backend::frontends::{self, Format, Unmapped};
+use std::env;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: games_xml [--config path] [--format emulationstation|launchbox] \
+                     [--dry-run] import|export <file.xml>";
+
+fn usage() -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, USAGE)
+}
+
+fn print_unmapped(unmapped: &[Unmapped]) {
+    if unmapped.is_empty() {
+        return;
+    }
+    println!("Not converted:");
+    for field in unmapped {
+        match field.game {
+            Some(ref id) => println!("  {}: {} = {:?}", id, field.field, field.value),
+            None => println!("  <{}> {:?}", field.field, field.value),
+        }
+    }
+}
+
+fn main() -> Result<(), io::Error> {
+    let mut toml_filepath: PathBuf = ["server_config.toml"].iter().collect();
+    let mut format = None;
+    let mut dry_run = false;
+    let mut positional = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => toml_filepath = args.next().map(PathBuf::from).ok_or_else(usage)?,
+            "--format" => {
+                let name = args.next().ok_or_else(usage)?;
+                format = Some(Format::parse(&name).ok_or_else(|| {
+                    io::Error::new(ErrorKind::InvalidInput, format!("Unknown format {:?}", name))
+                })?);
+            }
+            "--dry-run" | "-n" => dry_run = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 2 {
+        return Err(usage());
+    }
+    let xml_path = PathBuf::from(&positional[1]);
+
+    let mut config = Config::load(&toml_filepath)?;
+    match positional[0].as_str() {
+        "import" => {
+            let import = frontends::import(&xml_path, format, &config.static_dir_path())?;
+            if dry_run {
+                for &(ref id, ref game) in &import.games {
+                    println!("{} - {}\n    {}", id, game.name, game.exe_path.display());
+                }
+                println!("Found {} games, nothing was written", import.games.len());
+            } else {
+                let report = config.merge_launchable_games(&toml_filepath, import.games)?;
+                for id in &report.added {
+                    println!("Added {}", id);
+                }
+                for (id, reason) in &report.skipped {
+                    println!("Skipped {}: {}", id, reason);
+                }
+            }
+            print_unmapped(&import.unmapped);
+        }
+        "export" => {
+            let format = format.ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidInput, "export needs a --format".to_owned())
+            })?;
+            let (xml, unmapped) = frontends::export(format, &config.games, &xml_path, &config.static_dir_path())?;
+            write_atomically(&xml_path, &xml)?;
+            println!("Wrote {} games to {}", config.games.len(), xml_path.display());
+            print_unmapped(&unmapped);
+        }
+        _ => return Err(usage()),
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+/*
+ * Imports games installed through Steam into the server config.
+ *
+ * Usage: import_steam [--config path/to/server_config.toml] <steam dir> [--all | game_id...]
+ *
+ * With only a Steam directory, the installed games are listed along with the
+ * game ID each would be added under. Naming game IDs (or passing --all) adds
+ * those games to the config, launched with steam://rungameid/<appid>.
+ * Games that are already in the config are left alone.
+ */
+
+extern crate arclightning_backend;
+
+use arclightning_backend::config::{merge_conflict, Config};
+use arclightning_backend::steam;
+use std::env;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+const USAGE: &str =
+    "Usage: import_steam [--config path/to/server_config.toml] <steam dir> [--all | game_id...]";
+
+fn main() -> Result<(), io::Error> {
+    let mut toml_filepath: PathBuf = ["server_config.toml"].iter().collect();
+    let mut steam_dir = None;
+    let mut ids = Vec::new();
+    let mut all = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            toml_filepath = args
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, USAGE))?;
+        } else if arg == "--all" {
+            all = true;
+        } else if arg == "--help" || arg == "-h" {
+            println!("{}", USAGE);
+            return Ok(());
+        } else if steam_dir.is_none() {
+            steam_dir = Some(PathBuf::from(arg));
+        } else {
+            ids.push(arg);
+        }
+    }
+    let steam_dir = steam_dir.ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, USAGE))?;
+
+    let mut config = Config::load(&toml_filepath)?;
+    let found = steam::find_games(&steam_dir)?;
+
+    if !all && ids.is_empty() {
+        for game in &found {
+            let note = merge_conflict(&config.games, &game.id, &game.game)
+                .map(|reason| format!(" ({})", reason))
+                .unwrap_or_default();
+            println!(
+                "{} - {} (app {}){}\n    {}",
+                game.id,
+                game.game.name,
+                game.app_id,
+                note,
+                game.install_dir.display()
+            );
+        }
+        println!(
+            "Found {} games. Run again with the game IDs to import, or --all",
+            found.len()
+        );
+        return Ok(());
+    }
+
+    for id in &ids {
+        if !found.iter().any(|game| &game.id == id) {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("No installed Steam game has the ID {}", id),
+            ));
+        }
+    }
+    let selected = found
+        .into_iter()
+        .filter(|game| all || ids.contains(&game.id))
+        .map(|game| (game.id, game.game))
+        .collect();
+
+    let report = config.merge_launchable_games(&toml_filepath, selected)?;
+    for id in &report.added {
+        println!("Added {}", id);
+    }
+    for (id, reason) in &report.skipped {
+        println!("Skipped {}: {}", id, reason);
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+/*
+ * Upgrades a server config written for an older version of the server.
+ *
+ * Usage: migrate_config [--dry-run] [path/to/server_config.toml]
+ *
+ * The server upgrades old configs on its own when it starts; this shows
+ * what will change first. With --dry-run the changes are printed as a diff
+ * and nothing is written. Otherwise every changed file is backed up to
+ * <file>.v<old version>.bak before it is rewritten.
+ */
+
+extern crate arclightning_backend;
+
+use arclightning_backend::migrate::{self, CONFIG_VERSION};
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+fn main() -> Result<(), io::Error> {
+    let mut dry_run = false;
+    let mut toml_filepath: PathBuf = ["server_config.toml"].iter().collect();
+    for arg in env::args().skip(1) {
+        if arg == "--dry-run" || arg == "-n" {
+            dry_run = true;
+        } else {
+            toml_filepath = PathBuf::from(arg);
+        }
+    }
+
+    let upgrade = match migrate::plan(&toml_filepath)? {
+        Some(upgrade) => upgrade,
+        None => {
+            println!(
+                "{} is already at config_version {}",
+                toml_filepath.display(),
+                CONFIG_VERSION
+            );
+            return Ok(());
+        }
+    };
+
+    println!(
+        "Upgrading {} from config_version {} to {}:",
+        toml_filepath.display(),
+        upgrade.from,
+        CONFIG_VERSION
+    );
+    for step in &upgrade.steps {
+        println!("  - {}", step);
+    }
+    print!("{}", upgrade.diff());
+
+    if dry_run {
+        println!("Dry run, nothing was written");
+    } else {
+        upgrade.apply()?;
+        println!("Done, backups end in .v{}.bak", upgrade.from);
+    }
+    Ok(())
+}
@@ -2,12 +2,11 @@
  * This will be a file that can be run with 2 arguments,
  * the new password and the config file.
  *
- * It will write the new password to the config file, where
- * the config file is completely rewritten with the new password
+ * It will write the new password hash to auth.password_hash in the
+ * config file, leaving the rest of the file as it was
  *
- * Config file will need a password field as an Option<String>
- *
- * This will use the bcrypt crate.
+ * The hash is made by the password module, with the scheme and work
+ * factor set under [auth] in the config.
  */
 
 extern crate arclightning_backend;
@@ -21,12 +20,21 @@ extern crate serde_json;
 extern crate toml;
 
 use arclightning_backend::config::{Config, Game};
+use arclightning_backend::options::{self, Options};
 use std::io;
 use std::path::PathBuf;
 
 fn main() -> Result<(), io::Error> {
-    // Note: This path requires that set_password be run from project root directory
-    let toml_filepath: PathBuf = ["server_config.toml"].iter().collect();
+    // Overrides are rejected, so they are never written to the file.
+    let options = Options::config_only_from_env()?;
+    if options.help {
+        println!("Usage: set_password [options]\n\n{}", options::CONFIG_USAGE);
+        return Ok(());
+    }
+
+    // Without --config or ARCLIGHTNING_CONFIG this requires running from
+    // the project root directory.
+    let toml_filepath: PathBuf = options.config_path;
 
     let mut config: Config = Config::load(&toml_filepath)?;
 
@@ -37,9 +45,14 @@ fn main() -> Result<(), io::Error> {
         pass_check = rpassword::prompt_password_stdout("Re-type password: ")?;
     }
 
-    config.set_password(&pass);
+    config.set_password(&pass)?;
 
     config.write_to_path(&toml_filepath)?;
+    println!(
+        "Saved a {:?} password hash to {}",
+        config.auth.password_scheme,
+        toml_filepath.display()
+    );
 
     Ok(())
 }
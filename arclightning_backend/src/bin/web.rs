@@ -1,26 +1,67 @@
+extern crate arclightning_backend;
 extern crate futures;
 extern crate hyper;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
 
+use arclightning_backend::accounts::{Account, Accounts};
+use arclightning_backend::blocking;
+use arclightning_backend::config::Config;
+use arclightning_backend::launch_log::unix_time;
+use arclightning_backend::options::{self, Options};
+use arclightning_backend::password::HashPolicy;
+use arclightning_backend::sessions::{self, SessionPolicy, Sessions, WEB_SESSION_COOKIE};
+use arclightning_backend::throttle::{Throttle, ThrottlePolicy};
 use futures::{future, Stream};
+use hyper::header::{CONTENT_TYPE, RETRY_AFTER, SET_COOKIE, USER_AGENT};
 use hyper::rt::Future;
-use hyper::{Body, Error, Method, Request, Response, StatusCode};
-use std::collections::HashMap;
+use hyper::server::conn::AddrStream;
+use hyper::service::make_service_fn;
+use hyper::{Body, Error, Method, Request, Response, Server, StatusCode};
 use std::io::{self, ErrorKind};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
+use std::thread;
+use std::time::Duration;
 
 type ResponseFuture = Box<Future<Item = Response<Body>, Error = io::Error> + Send>;
 
+// Serves the phone-facing web app, where players have accounts of their
+// own. Shares [auth] settings with the cabinet server but not its sessions.
 #[derive(Debug, Clone)]
-pub struct WebRouter {}
+pub struct WebRouter {
+    accounts: Accounts,
+    sessions: Sessions,
+    // Failed sign-ins, shared by every clone
+    throttle: Throttle,
+    // Registrations, which count against a client even when they succeed
+    registrations: Throttle,
+    // The address of the connection being served, see for_client
+    client: Option<IpAddr>,
+    session_policy: SessionPolicy,
+    throttle_policy: ThrottlePolicy,
+    hash_policy: HashPolicy,
+    secure_cookie: bool,
+}
 
 #[derive(Debug, Deserialize, Clone)]
 struct StartGameRequest {
     id: String,
 }
+#[derive(Debug, Deserialize, Clone)]
+struct AccountRequest {
+    username: String,
+    password: String,
+}
+
+fn user_agent(request: &Request<Body>) -> Option<String> {
+    request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .map(str::to_owned)
+}
 
 impl hyper::service::Service for WebRouter {
     type ReqBody = Body;
@@ -40,13 +81,42 @@ impl hyper::service::NewService for WebRouter {
     type Future = Box<Future<Item = Self::Service, Error = Self::InitError> + Send>;
     type InitError = Error;
     fn new_service(&self) -> Self::Future {
-        Box::new(future::ok(Self {}))
+        Box::new(future::ok(self.clone()))
     }
 }
 
 impl WebRouter {
-    pub fn new() -> Self {
-        WebRouter {}
+    pub fn new(config: &Config) -> Result<Self, io::Error> {
+        Ok(WebRouter {
+            accounts: Accounts::load(config.accounts_path.clone())?,
+            sessions: Sessions::new(),
+            throttle: Throttle::new().with_audit_log(config.auth.audit_log_path.clone()),
+            registrations: Throttle::new(),
+            client: None,
+            session_policy: config.auth.session_policy(),
+            throttle_policy: config.auth.throttle_policy(),
+            hash_policy: config.auth.hash_policy(),
+            secure_cookie: config.auth.secure_cookie,
+        })
+    }
+
+    // A router for a connection from `client`. Failed sign-ins are counted
+    // per client address.
+    pub fn for_client(&self, client: IpAddr) -> Self {
+        let mut router = self.clone();
+        router.client = Some(client);
+        router
+    }
+
+    // Removes expired sessions every `interval`
+    pub fn purge_sessions_every(&self, interval: Duration) {
+        let router = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if let Err(err) = router.sessions.purge(&router.session_policy, unix_time()) {
+                println!("Failed to purge sessions: {}", err);
+            }
+        });
     }
 
     fn invalid_endpoint(&self) -> ResponseFuture {
@@ -63,59 +133,257 @@ impl WebRouter {
         ))
     }
 
-    // TODO: implement these functions
+    fn json_response(&self, status: StatusCode, body: String) -> Result<Response<Body>, io::Error> {
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .map_err(|err| {
+                io::Error::new(
+                    ErrorKind::Other,
+                    format!("An error occured when building a response: {}", err),
+                )
+            })
+    }
 
-    fn register(&self, request: Request<Body>) -> ResponseFuture {
-        // TODO: parse body into username and password
-        /*
-        let response = request.body().concat2().map_err(|err| {
-            io::Error::new(
-                ErrorKind::Other,
-                format!("Failed to parse byte string: {}", err),
-            )
-        });
-        */
+    // Rejected usernames and passwords are the client's fault; anything
+    // else is ours
+    fn error_response(&self, err: &io::Error) -> Result<Response<Body>, io::Error> {
+        let status = match err.kind() {
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::AlreadyExists => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        self.json_response(
+            status,
+            json!({ "success": false, "error": err.to_string() }).to_string(),
+        )
+    }
 
-        // TODO: wip
-        let response = future::result(
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("TODO".to_owned()))
-                .map_err(|err| {
-                    io::Error::new(
-                        ErrorKind::Other,
-                        format!("An error occured when constructing TODO: {}", err),
-                    )
-                }),
-        );
+    // Reads the {username, password} body of register and signin and passes
+    // it to `handler` off the reactor. A missing or malformed body is
+    // answered with a 422, like any other rejected input.
+    fn account_request<F>(&self, request: Request<Body>, handler: F) -> ResponseFuture
+    where
+        F: FnOnce(AccountRequest) -> Result<Response<Body>, io::Error> + Send + 'static,
+    {
+        let router = self.clone();
+        let response = request
+            .into_body()
+            .concat2()
+            .map_err(|err| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Failed to read the request body: {}", err),
+                )
+            })
+            .and_then(|body| {
+                serde_json::from_slice(&body)
+                    .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))
+            })
+            .then(move |body| match body {
+                Ok(body) => future::Either::A(blocking::run(move || handler(body))),
+                Err(err) => future::Either::B(future::result(router.error_response(&err))),
+            });
         Box::new(response)
     }
 
-    fn signin(&self, request: Request<Body>) -> ResponseFuture {
-        // TODO: parse body into username and password
-        /*
-        let response = request.body().concat2().map_err(|err| {
-            io::Error::new(
-                ErrorKind::Other,
-                format!("Failed to parse byte string: {}", err),
+    // Starts a session for `account` and hands it to the client in a cookie
+    fn signed_in(
+        &self,
+        account: &Account,
+        client: Option<String>,
+    ) -> Result<Response<Body>, io::Error> {
+        let token = self.sessions.create_for_user(
+            &self.session_policy,
+            &account.username,
+            client,
+            unix_time(),
+        )?;
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                SET_COOKIE,
+                sessions::session_cookie(
+                    WEB_SESSION_COOKIE,
+                    &token,
+                    &self.session_policy,
+                    self.secure_cookie,
+                )
+                .as_str(),
             )
-        });
-        */
-        // TODO: wip
-        let response = future::result(
+            .body(Body::from(
+                json!({ "success": true, "username": account.username }).to_string(),
+            ))
+            .map_err(|err| {
+                io::Error::new(
+                    ErrorKind::Other,
+                    format!("An error occured when building a response: {}", err),
+                )
+            })
+    }
+
+    // Refuses a sign-in or registration from a client that has tried too often
+    fn too_many_attempts(&self, retry_after: u64) -> Result<Response<Body>, io::Error> {
+        Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(CONTENT_TYPE, "application/json")
+            .header(RETRY_AFTER, retry_after.to_string().as_str())
+            .body(Body::from(
+                json!({
+                    "success": false,
+                    "error": "Too many attempts, try again later",
+                    "retry_after": retry_after,
+                })
+                .to_string(),
+            ))
+            .map_err(|err| {
+                io::Error::new(
+                    ErrorKind::Other,
+                    format!("An error occured when building a response: {}", err),
+                )
+            })
+    }
+
+    // Creates an account from {username, password} and signs it in. Every
+    // registration counts as a failed attempt, so a client can't create
+    // accounts or keep the server hashing passwords as fast as it likes.
+    fn register(&self, request: Request<Body>) -> ResponseFuture {
+        let address = self
+            .client
+            .map_or_else(|| "unknown".to_owned(), |ip| ip.to_string());
+        let router = self.clone();
+        let client = user_agent(&request);
+
+        // Hashing and saving the accounts file stay off the reactor
+        self.account_request(request, move |body| {
+            let now = unix_time();
+            if let Err(retry_after) =
+                router
+                    .registrations
+                    .begin_attempt(&address, &router.throttle_policy, now)?
+            {
+                return router.too_many_attempts(retry_after);
+            }
+
+            match router
+                .accounts
+                .register(&body.username, &body.password, &router.hash_policy, now)
+            {
+                Ok(account) => {
+                    println!("Registered account {:?}", account.username);
+                    router.signed_in(&account, client)
+                }
+                Err(err) => router.error_response(&err),
+            }
+        })
+    }
+
+    // Signs in with {username, password}. Clients that keep failing have to
+    // wait longer and longer between tries, as on the cabinet.
+    fn signin(&self, request: Request<Body>) -> ResponseFuture {
+        let address = self
+            .client
+            .map_or_else(|| "unknown".to_owned(), |ip| ip.to_string());
+        let router = self.clone();
+        let client = user_agent(&request);
+
+        // Hashing, the throttle and its audit log all stay off the reactor
+        self.account_request(request, move |body| {
+            let attempt = match router.throttle.begin_attempt(
+                &address,
+                &router.throttle_policy,
+                unix_time(),
+            )? {
+                Ok(attempt) => attempt,
+                Err(retry_after) => return router.too_many_attempts(retry_after),
+            };
+
+            match router
+                .accounts
+                .sign_in(&body.username, &body.password, &router.hash_policy)?
+            {
+                Some(account) => {
+                    router.throttle.success(&address, attempt)?;
+                    router.signed_in(&account, client)
+                }
+                None => {
+                    router
+                        .throttle
+                        .failure(&address, &router.throttle_policy, unix_time())?;
+                    router.json_response(StatusCode::OK, r#"{"success":false}"#.to_owned())
+                }
+            }
+        })
+    }
+
+    // Ends the session making the request and removes its cookie
+    fn signout(&self, request: &Request<Body>) -> ResponseFuture {
+        let removed = match sessions::request_token(request.headers(), WEB_SESSION_COOKIE) {
+            Some(token) => self.sessions.remove(&token),
+            None => Ok(false),
+        };
+
+        Box::new(future::result(removed.and_then(|removed| {
             Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("TODO".to_owned()))
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, "application/json")
+                .header(
+                    SET_COOKIE,
+                    sessions::expired_cookie(WEB_SESSION_COOKIE, self.secure_cookie).as_str(),
+                )
+                .body(Body::from(json!({ "success": removed }).to_string()))
                 .map_err(|err| {
                     io::Error::new(
                         ErrorKind::Other,
-                        format!("An error occured when constructing TODO: {}", err),
+                        format!("An error occured when building a response: {}", err),
                     )
-                }),
-        );
-        Box::new(response)
+                })
+        })))
+    }
+
+    // The account the request is signed in to, if any
+    fn signed_in_account(&self, request: &Request<Body>) -> Result<Option<Account>, io::Error> {
+        let token = match sessions::request_token(request.headers(), WEB_SESSION_COOKIE) {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        if !self
+            .sessions
+            .check(&token, &self.session_policy, unix_time())?
+        {
+            return Ok(None);
+        }
+        match self.sessions.user_of(&token)? {
+            Some(username) => self.accounts.get(&username),
+            None => Ok(None),
+        }
+    }
+
+    // The signed in player's account, without its password hash
+    fn account(&self, request: &Request<Body>) -> ResponseFuture {
+        let response = self
+            .signed_in_account(request)
+            .and_then(|account| match account {
+                Some(account) => self.json_response(
+                    StatusCode::OK,
+                    json!({
+                        "success": true,
+                        "username": account.username,
+                        "created": account.created,
+                    })
+                    .to_string(),
+                ),
+                None => {
+                    self.json_response(StatusCode::FORBIDDEN, r#"{"success":false}"#.to_owned())
+                }
+            });
+        Box::new(future::result(response))
     }
 
+    // TODO: implement these functions
+
     fn check_in(&self, request: Request<Body>) -> ResponseFuture {
         // TODO: parse body into key
         /*
@@ -195,6 +463,8 @@ impl WebRouter {
         match (request.method(), request.uri().path()) {
             (&Method::POST, "/api/v1/register") => self.register(request),
             (&Method::POST, "/api/v1/signin") => self.signin(request),
+            (&Method::POST, "/api/v1/signout") => self.signout(&request),
+            (&Method::GET, "/api/v1/account") => self.account(&request),
             (&Method::POST, "/api/v1/check_in") => self.check_in(request),
             (&Method::POST, "/api/v1/check_out") => self.check_out(request),
             (&Method::POST, "/api/v1/check_settings") => self.check_settings(request),
@@ -203,4 +473,31 @@ impl WebRouter {
     }
 }
 
-fn main() {}
+fn main() -> Result<(), io::Error> {
+    let options = Options::from_env()?;
+    if options.help {
+        println!("Usage: web [options]\n\n{}", options::USAGE);
+        return Ok(());
+    }
+
+    // Shares the config file with the cabinet server but listens on
+    // web_listen_port, which --port overrides here
+    let mut config = options.load_config()?;
+    if let Some(port) = options.port {
+        config.web_listen_port = port;
+    }
+    let addr = config.web_listen_addr();
+
+    let router = WebRouter::new(&config)?;
+    router.purge_sessions_every(Duration::from_secs(60));
+
+    let server = Server::bind(&addr)
+        .serve(make_service_fn(move |socket: &AddrStream| {
+            future::ok::<_, io::Error>(router.for_client(socket.remote_addr().ip()))
+        }))
+        .map_err(|err| eprintln!("server error: {}", err));
+
+    println!("Listening on http://{}", addr);
+    hyper::rt::run(server);
+    Ok(())
+}
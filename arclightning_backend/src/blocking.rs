@@ -0,0 +1,33 @@
+// Slow work, like hashing passwords or writing files, must not run on the
+// reactor: it would hold up every other request while it runs. `run` does
+// the work on a thread of its own and hands the result back as a future.
+
+use futures::sync::oneshot;
+use futures::{future, Future};
+use std::io::{self, ErrorKind};
+use std::thread;
+
+pub type BlockingFuture<T> = Box<Future<Item = T, Error = io::Error> + Send>;
+
+pub fn run<F, T>(work: F) -> BlockingFuture<T>
+where
+    F: FnOnce() -> Result<T, io::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    let spawned = thread::Builder::new()
+        .name("blocking".to_owned())
+        .spawn(move || {
+            // The request may have been dropped in the meantime
+            let _ = sender.send(work());
+        });
+    if let Err(err) = spawned {
+        return Box::new(future::err(err));
+    }
+
+    Box::new(
+        receiver
+            .map_err(|_| io::Error::new(ErrorKind::Other, "Blocking work panicked".to_owned()))
+            .and_then(future::result),
+    )
+}
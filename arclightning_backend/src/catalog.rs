@@ -0,0 +1,161 @@
+// Filtering and sorting for /api/v1/list_games.
+//
+// The query string picks which games are listed, e.g.
+// `?genre=fighter&players=2&sort=year`. Unknown parameters are ignored.
+// Games always come back in a fixed order, so the UI can rely on it.
+
+use config::{Game, Input, PlayMode};
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    // sort_order, then name
+    Order,
+    Name,
+    Year,
+    Players,
+    Developer,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Query {
+    // Matches any of a game's tags, ignoring case
+    pub genre: Option<String>,
+    // Games that can be played by this many players at once
+    pub players: Option<u32>,
+    pub mode: Option<PlayMode>,
+    pub input: Option<Input>,
+    pub developer: Option<String>,
+    pub year: Option<u32>,
+    pub sort: Option<SortKey>,
+    pub descending: bool,
+}
+
+// Enum values are spelled the same as in the config, e.g. `light_gun`
+fn parse_enum<T: DeserializeOwned>(key: &str, value: &str) -> Result<T, String> {
+    serde_json::from_value(json!(value)).map_err(|_| format!("Invalid {} {:?}", key, value))
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {} {:?}: expected a number", key, value))
+}
+
+impl Query {
+    // Reads the query from parameters looked up with `param`
+    pub fn parse<F>(param: F) -> Result<Query, String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut query = Query {
+            genre: param("genre"),
+            developer: param("developer"),
+            ..Default::default()
+        };
+        if let Some(players) = param("players") {
+            query.players = Some(parse_number("players", &players)?);
+        }
+        if let Some(year) = param("year") {
+            query.year = Some(parse_number("year", &year)?);
+        }
+        if let Some(mode) = param("mode") {
+            query.mode = Some(parse_enum("mode", &mode)?);
+        }
+        if let Some(input) = param("input") {
+            query.input = Some(parse_enum("input", &input)?);
+        }
+        // A leading '-' sorts in descending order
+        if let Some(sort) = param("sort") {
+            query.descending = sort.starts_with('-');
+            query.sort = Some(match sort.trim_start_matches('-') {
+                "order" => SortKey::Order,
+                "name" => SortKey::Name,
+                "year" => SortKey::Year,
+                "players" => SortKey::Players,
+                "developer" => SortKey::Developer,
+                _ => return Err(format!("Invalid sort {:?}", sort)),
+            });
+        }
+        Ok(query)
+    }
+
+    pub fn matches(&self, game: &Game) -> bool {
+        let eq_ignore_case = |wanted: &Option<String>, value: Option<&String>| match *wanted {
+            Some(ref wanted) => value.map_or(false, |value| value.eq_ignore_ascii_case(wanted)),
+            None => true,
+        };
+
+        let min_players = game.min_players.unwrap_or(1);
+        let max_players = game.max_players.unwrap_or(min_players);
+        (self.genre.is_none() || game.tags.iter().any(|tag| eq_ignore_case(&self.genre, Some(tag))))
+            && eq_ignore_case(&self.developer, game.developer.as_ref())
+            && self
+                .players
+                .map_or(true, |players| min_players <= players && players <= max_players)
+            && self.mode.map_or(true, |mode| game.play_modes.contains(&mode))
+            && self.input.map_or(true, |input| game.inputs.contains(&input))
+            && self.year.map_or(true, |year| game.release_year == Some(year))
+    }
+
+    // Orders two games by the sort key. Games missing the value sorted on
+    // come last either way, and ties are broken by name, then ID.
+    pub fn compare(&self, a: (&str, &Game), b: (&str, &Game)) -> Ordering {
+        fn by_value<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) if descending => b.cmp(&a),
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+
+        let (a_id, a) = a;
+        let (b_id, b) = b;
+        let descending = self.descending;
+
+        let ordering = match self.sort.unwrap_or(SortKey::Order) {
+            SortKey::Order => by_value(a.sort_order, b.sort_order, descending),
+            SortKey::Name => Ordering::Equal,
+            SortKey::Year => by_value(a.release_year, b.release_year, descending),
+            SortKey::Players => by_value(
+                a.max_players.or(a.min_players),
+                b.max_players.or(b.min_players),
+                descending,
+            ),
+            SortKey::Developer => by_value(
+                a.developer.as_ref().map(|name| name.to_lowercase()),
+                b.developer.as_ref().map(|name| name.to_lowercase()),
+                descending,
+            ),
+        };
+        ordering.then_with(|| {
+            let by_name = a
+                .name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a_id.cmp(b_id));
+            if descending && self.sort == Some(SortKey::Name) {
+                by_name.reverse()
+            } else {
+                by_name
+            }
+        })
+    }
+
+    // The games that match, in order
+    pub fn apply<'a, I>(&self, games: I) -> Vec<(&'a String, &'a Game)>
+    where
+        I: IntoIterator<Item = (&'a String, &'a Game)>,
+    {
+        let mut games: Vec<(&String, &Game)> = games
+            .into_iter()
+            .filter(|&(_, game)| self.matches(game))
+            .collect();
+        games.sort_by(|a, b| self.compare((a.0, a.1), (b.0, b.1)));
+        games
+    }
+}
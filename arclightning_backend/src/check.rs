@@ -0,0 +1,413 @@
+// Diagnostics for server_config.toml, reported by the check_config binary
+
+use config::{self, Config, Game};
+use migrate;
+use password;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::path::{Path, PathBuf};
+use toml;
+use toml_edit::Document;
+use validate;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    // 1-based, when the problem can be tied to a place in the file
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(
+                f,
+                "{}:{}:{}: {}",
+                self.path.display(),
+                line,
+                column,
+                self.message
+            ),
+            (Some(line), None) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            _ => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+// Position of `key` in the `[table]` section of a TOML document, or at the
+// top level when `table` is None
+fn locate(source: &str, table: Option<&str>, key: &str) -> Option<(usize, usize)> {
+    let mut in_table = table.is_none();
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            let header = trimmed.trim_matches(|c| c == '[' || c == ']' || c == ' ');
+            let header = header.split(']').next().unwrap_or("");
+            if table == Some(header) && key.is_empty() {
+                return Some((index + 1, line.len() - trimmed.len() + 1));
+            }
+            in_table = table == Some(header);
+            continue;
+        }
+
+        if in_table && !key.is_empty() {
+            let name = trimmed.split('=').next().unwrap_or("").trim();
+            if trimmed.contains('=') && name.trim_matches('"') == key {
+                return Some((index + 1, line.len() - trimmed.len() + 1));
+            }
+        }
+    }
+    None
+}
+
+// Position of a dotted key path such as `games.touhou` or
+// `games.touhou.exe_path`, whether it names a table or a key
+fn locate_path(source: &str, path: &str) -> Option<(usize, usize)> {
+    locate(source, Some(path), "").or_else(|| match path.rfind('.') {
+        Some(dot) => locate(source, Some(&path[..dot]), &path[dot + 1..]),
+        None => locate(source, None, path),
+    })
+}
+
+// Game IDs whose `[games.<id>]` table appears more than once, with the line
+// of each repeat
+fn duplicate_games(source: &str) -> Vec<(String, usize)> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("[games.") && !trimmed.starts_with("[[") {
+            let id = trimmed["[games.".len()..]
+                .split(']')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .trim_matches('"')
+                .to_owned();
+            // Sub-tables such as [games.touhou.env] belong to their game
+            if id.contains('.') {
+                continue;
+            }
+            if seen.contains_key(&id) {
+                duplicates.push((id, index + 1));
+            } else {
+                seen.insert(id, index + 1);
+            }
+        }
+    }
+    duplicates
+}
+
+// The key a TOML error about a value is for, if it names one
+fn error_key(err: &toml::de::Error) -> Option<String> {
+    format!("{}", err)
+        .split("for key `")
+        .nth(1)
+        .and_then(|key| key.split('`').next())
+        .map(str::to_owned)
+}
+
+// Position of a TOML error. Errors about values, such as missing fields or
+// out of range numbers, have no position of their own, so they point at the
+// key or table they are about.
+fn error_position(source: &str, err: &toml::de::Error) -> Option<(usize, usize)> {
+    let position = err.line_col().map(|(line, column)| (line + 1, column + 1));
+    position.or_else(|| error_key(err).and_then(|key| locate_path(source, &key)))
+}
+
+fn read_source(path: &Path) -> Result<String, Diagnostic> {
+    let mut source = String::new();
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut source)) {
+        Ok(_) => Ok(source),
+        Err(err) => Err(Diagnostic {
+            path: path.to_path_buf(),
+            line: None,
+            column: None,
+            message: format!("{}", err),
+        }),
+    }
+}
+
+// Checks a config document. Documents written for an older config_version
+// are checked as they will be after the upgrade, without line numbers since
+// those would point into the upgraded text.
+pub fn check_source(path: &Path, source: &str) -> Vec<Diagnostic> {
+    let mut document: Document = match source.parse() {
+        Ok(document) => document,
+        Err(_) => return check_current(path, source),
+    };
+    let from = match migrate::version(&document) {
+        Ok(version) => version,
+        Err(err) => {
+            return vec![Diagnostic {
+                path: path.to_path_buf(),
+                line: locate(source, None, "config_version").map(|(line, _)| line),
+                column: locate(source, None, "config_version").map(|(_, column)| column),
+                message: format!("{}", err),
+            }]
+        }
+    };
+    if from == migrate::CONFIG_VERSION {
+        return check_current(path, source);
+    }
+
+    let mut diagnostics = vec![Diagnostic {
+        path: path.to_path_buf(),
+        line: None,
+        column: None,
+        message: format!(
+            "config_version {} is outdated and will be upgraded to {} when the server \
+             starts; preview the changes with migrate_config --dry-run",
+            from,
+            migrate::CONFIG_VERSION
+        ),
+    }];
+    if migrate::migrate_document(&mut document).is_ok() {
+        let upgraded = document.to_string();
+        for mut diagnostic in check_current(path, &upgraded) {
+            if diagnostic.path == path {
+                diagnostic.line = None;
+                diagnostic.column = None;
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+fn check_current(path: &Path, source: &str) -> Vec<Diagnostic> {
+    let diagnostic = |position: Option<(usize, usize)>, message: String| Diagnostic {
+        path: path.to_path_buf(),
+        line: position.map(|(line, _)| line),
+        column: position.map(|(_, column)| column),
+        message,
+    };
+    let mut diagnostics = Vec::new();
+
+    for (id, line) in duplicate_games(source) {
+        diagnostics.push(diagnostic(
+            Some((line, 1)),
+            format!("duplicate game ID `{}`", id),
+        ));
+    }
+
+    let mut document: toml::Value = match toml::from_str(source) {
+        Ok(document) => document,
+        Err(err) => {
+            diagnostics.push(diagnostic(error_position(source, &err), format!("{}", err)));
+            return diagnostics;
+        }
+    };
+
+    // Each game is read on its own, so one bad game doesn't hide the
+    // problems of the others
+    let game_values = match document
+        .as_table_mut()
+        .and_then(|table| table.remove("games"))
+    {
+        Some(toml::Value::Table(games)) => games,
+        Some(_) => {
+            diagnostics.push(diagnostic(
+                locate(source, None, "games"),
+                "games must be a table".to_owned(),
+            ));
+            toml::value::Table::new()
+        }
+        None => toml::value::Table::new(),
+    };
+    let mut config: Config = match document.try_into() {
+        Ok(config) => config,
+        Err(err) => {
+            diagnostics.push(diagnostic(error_position(source, &err), format!("{}", err)));
+            return diagnostics;
+        }
+    };
+    let inline_ids: HashSet<String> = game_values.keys().cloned().collect();
+    for (id, game_value) in game_values {
+        let table = format!("games.{}", id);
+        match game_value.try_into::<Game>() {
+            Ok(game) => {
+                config.games.insert(id, game);
+            }
+            Err(err) => diagnostics.push(diagnostic(
+                error_key(&err)
+                    .and_then(|key| locate_path(source, &format!("{}.{}", table, key)))
+                    .or_else(|| locate(source, Some(&table), "")),
+                format!("{}: {}", table, err),
+            )),
+        }
+    }
+
+    // Paths in the config are relative to the config file
+    config.resolve_paths(path);
+    let static_dir = config.static_dir_path();
+
+    // Drop-in games are checked against their own file
+    let mut game_sources: HashMap<String, (PathBuf, String)> = HashMap::new();
+    if let Some(dir) = config.games_dir_path(path) {
+        let paths = match config::games_dir_files(&dir) {
+            Ok(paths) => paths,
+            Err(err) => {
+                diagnostics.push(diagnostic(
+                    locate(source, None, "games_dir"),
+                    format!("games_dir {:?}: {}", dir, err),
+                ));
+                Vec::new()
+            }
+        };
+        for game_path in paths {
+            let game_source = match read_source(&game_path) {
+                Ok(game_source) => game_source,
+                Err(err) => {
+                    diagnostics.push(err);
+                    continue;
+                }
+            };
+            let id = config::game_id(&game_path);
+            let file_diagnostic = |position: Option<(usize, usize)>, message| Diagnostic {
+                path: game_path.clone(),
+                line: position.map(|(line, _)| line),
+                column: position.map(|(_, column)| column),
+                message,
+            };
+            if !config::is_valid_game_id(&id) {
+                diagnostics.push(file_diagnostic(
+                    None,
+                    "the file name is not a valid game ID".to_owned(),
+                ));
+                continue;
+            }
+            if inline_ids.contains(&id) {
+                diagnostics.push(file_diagnostic(
+                    Some((1, 1)),
+                    format!(
+                        "game `{}` is also defined in {}",
+                        id,
+                        path.display()
+                    ),
+                ));
+                continue;
+            }
+            match toml::from_str::<Game>(&game_source) {
+                Ok(game) => {
+                    config.games.insert(id.clone(), game);
+                    game_sources.insert(id, (game_path, game_source));
+                }
+                Err(err) => diagnostics.push(file_diagnostic(
+                    error_position(&game_source, &err),
+                    format!("{}", err),
+                )),
+            }
+        }
+    }
+
+    // Where the problems of a game are reported: its table in the config,
+    // or the top level of its drop-in file
+    let game_position = |id: &str, key: &str| -> (PathBuf, Option<(usize, usize)>) {
+        match game_sources.get(id) {
+            Some(&(ref game_path, ref game_source)) => (
+                game_path.clone(),
+                locate(game_source, None, key).or(Some((1, 1))),
+            ),
+            None => {
+                let table = format!("games.{}", id);
+                let position = locate(source, Some(&table), key)
+                    .or_else(|| locate(source, Some(&table), ""));
+                (path.to_path_buf(), position)
+            }
+        }
+    };
+
+    if config.listen_port == 0 {
+        diagnostics.push(diagnostic(
+            locate(source, None, "listen_port"),
+            "listen_port must be between 1 and 65535".to_owned(),
+        ));
+    }
+    if config.web_listen_port == 0 {
+        diagnostics.push(diagnostic(
+            locate(source, None, "web_listen_port"),
+            "web_listen_port must be between 1 and 65535".to_owned(),
+        ));
+    } else if config.web_listen_port == config.listen_port {
+        diagnostics.push(diagnostic(
+            locate(source, None, "web_listen_port"),
+            "web_listen_port must differ from listen_port".to_owned(),
+        ));
+    }
+
+    if !static_dir.is_dir() {
+        diagnostics.push(diagnostic(
+            locate(source, None, "static_dir"),
+            format!("static_dir {:?} is not a directory", config.static_dir),
+        ));
+    }
+
+    if let Some(ref hash) = config.auth.password_hash {
+        if !password::is_valid_hash(hash) {
+            diagnostics.push(diagnostic(
+                locate(source, Some("auth"), "password_hash"),
+                "auth.password_hash is not a recognized password hash; set it with set_password"
+                    .to_owned(),
+            ));
+        }
+    }
+
+    // The games are validated one by one below, each reporting its own
+    // first problem
+    let games = mem::replace(&mut config.games, HashMap::new());
+    let validated = config.validate();
+    config.games = games;
+    if let Err(err) = validated {
+        let message = format!("{}", err);
+        let key = message.split(':').next().unwrap_or("").to_owned();
+        diagnostics.push(diagnostic(locate_path(source, &key), message));
+    }
+
+    let mut ids: Vec<&String> = config.games.keys().collect();
+    ids.sort();
+    for id in &ids {
+        if let Err(err) = config.games[*id].validate(id) {
+            let message = format!("{}", err);
+            let key = message.split(':').next().unwrap_or("");
+            let field = key
+                .get(format!("games.{}.", id).len()..)
+                .filter(|_| key.starts_with(&format!("games.{}.", id)))
+                .unwrap_or("");
+            let (game_path, position) = game_position(id, field);
+            diagnostics.push(Diagnostic {
+                path: game_path,
+                line: position.map(|(line, _)| line),
+                column: position.map(|(_, column)| column),
+                message,
+            });
+        }
+    }
+    for id in ids {
+        for problem in validate::check_game(&config.games[id], &static_dir) {
+            // Problems start with the name of the key they are about
+            let key = problem.split(' ').next().unwrap_or("");
+            let (game_path, position) = game_position(id, key);
+            diagnostics.push(Diagnostic {
+                path: game_path,
+                line: position.map(|(line, _)| line),
+                column: position.map(|(_, column)| column),
+                message: format!("games.{}: {}", id, problem),
+            });
+        }
+    }
+
+    diagnostics
+}
+
+pub fn check_file(path: &Path) -> Vec<Diagnostic> {
+    match read_source(path) {
+        Ok(source) => check_source(path, &source),
+        Err(err) => vec![err],
+    }
+}
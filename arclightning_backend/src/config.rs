@@ -1,61 +1,743 @@
-use bcrypt::{hash, verify};
-use std::collections::HashMap;
-use std::fs::File;
+use document;
+use launcher::check_template;
+use migrate;
+use password::{self, HashPolicy, Scheme};
+use sessions::SessionPolicy;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, ErrorKind, Read, Write};
-use std::path::PathBuf;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use throttle::ThrottlePolicy;
 use toml;
+use validate;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Config {
+    // Documents from older versions are upgraded on load, see migrate.rs
+    #[serde(default = "default_config_version")]
+    pub config_version: u32,
+    // Only the local machine can connect by default
+    #[serde(default = "default_listen_address")]
+    pub listen_address: IpAddr,
     pub listen_port: u16,
+    // Where the web binary serves the phone-facing app
+    #[serde(default = "default_web_listen_port")]
+    pub web_listen_port: u16,
     pub static_dir: PathBuf,
-    pub password: Option<String>,
+    // Default limit for games that don't set max_session_minutes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_session_minutes: Option<u64>,
+    // How long before the limit players are warned, at most half the session
+    #[serde(default = "default_session_warning_minutes")]
+    pub session_warning_minutes: u64,
+    // Only end sessions while other players are on the waitlist
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub limit_only_when_waiting: bool,
+    // Output of each launched game is captured here
+    #[serde(default = "default_log_dir")]
+    pub log_dir: PathBuf,
+    // Older launch logs are deleted beyond this count
+    #[serde(default = "default_max_launch_logs")]
+    pub max_launch_logs: usize,
+    // Every finished game is appended here for play statistics. Crash
+    // counts are kept next to it in game_health.json.
+    #[serde(default = "default_history_path")]
+    pub history_path: PathBuf,
+    // Player accounts for the web app
+    #[serde(default = "default_accounts_path")]
+    pub accounts_path: PathBuf,
+    // Games exiting sooner than this after launch are treated as crashed
+    #[serde(default = "default_crash_grace_secs")]
+    pub crash_grace_secs: u64,
+    // Launch a crashed game once more before giving up
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub retry_on_crash: bool,
+    // Consecutive crashes before a game is listed as unavailable
+    #[serde(default = "default_max_consecutive_crashes")]
+    pub max_consecutive_crashes: u32,
+    // Leave games with missing files or repeated crashes out of list_games
+    // instead of listing them as unavailable
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub hide_unavailable_games: bool,
+    // Stopped games get this long to exit after SIGTERM before SIGKILL
+    #[serde(default = "default_kill_grace_secs")]
+    pub kill_grace_secs: u64,
+    // Directory of drop-in game files, relative to the config file. Each
+    // `<id>.toml` in it holds a single game, merged with the games defined
+    // below.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub games_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub auth: Auth,
+    // Hooks for games that don't define their own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_launch: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_exit: Vec<Hook>,
+    #[serde(default)]
     pub games: HashMap<String, Game>,
+    // Games that were read from games_dir, by ID. They are left out when
+    // the config is written back.
+    #[serde(skip)]
+    pub game_files: HashMap<String, PathBuf>,
+    // The directory of the config file, which relative paths like static_dir
+    // are resolved against. Set by Config::load.
+    #[serde(skip)]
+    pub config_dir: PathBuf,
+}
+
+// Documents without a config_version predate versioning
+fn default_config_version() -> u32 {
+    1
+}
+
+fn default_listen_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))
+}
+
+fn default_web_listen_port() -> u16 {
+    3001
+}
+
+fn default_session_warning_minutes() -> u64 {
+    2
+}
+
+fn default_log_dir() -> PathBuf {
+    PathBuf::from("logs")
+}
+
+fn default_max_launch_logs() -> usize {
+    50
+}
+
+fn default_history_path() -> PathBuf {
+    PathBuf::from("play_history.jsonl")
+}
+
+fn default_accounts_path() -> PathBuf {
+    PathBuf::from("accounts.toml")
+}
+
+fn default_crash_grace_secs() -> u64 {
+    5
+}
+
+fn default_max_consecutive_crashes() -> u32 {
+    3
+}
+
+fn default_kill_grace_secs() -> u64 {
+    5
 }
 
 impl Config {
+    pub fn listen_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.listen_address, self.listen_port)
+    }
+
+    pub fn web_listen_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.listen_address, self.web_listen_port)
+    }
+
+    // Hashes with the scheme and work factor in [auth]
     pub fn set_password(&mut self, password: &str) -> std::io::Result<()> {
-        // The cost is set to 4 for our demo purposes to keep speed up
-        let hashed_password = hash(&password, 4).map_err(|err| {
-            io::Error::new(
-                ErrorKind::Other,
-                format!("An error occured when serializing config toml: {}", err),
-            )
-        })?;
-        self.password = Some(hashed_password);
+        let hashed_password = password::hash(password, &self.auth.hash_policy())?;
+        self.auth.password_hash = Some(hashed_password);
         Ok(())
     }
 
     pub fn write_to_path(&self, toml_filepath: &PathBuf) -> std::io::Result<()> {
-        let mut file = File::create(toml_filepath)?;
+        let mut inline = self.clone();
+        inline
+            .games
+            .retain(|id, _| !self.game_files.contains_key(id));
 
-        let toml_string = toml::to_string(&self).map_err(|err| {
-            io::Error::new(
-                ErrorKind::Other,
-                format!("An error occured when serializing config toml: {}", err),
-            )
-        })?;
+        // Only the keys that changed are rewritten, keeping comments and
+        // layout of the rest of the file
+        let toml_string = document::to_toml_string(toml_filepath, &inline)?;
+        write_atomically(toml_filepath, &toml_string)
+    }
+
+    // Adds or replaces a game and saves it: to its own file if it came from
+    // games_dir or games_dir is set, otherwise to the config file
+    pub fn save_game(&mut self, toml_filepath: &PathBuf, id: &str, game: Game) -> io::Result<()> {
+        game.validate(id)?;
+
+        let game_file = match (self.game_files.get(id), self.games_dir_path(toml_filepath)) {
+            (Some(path), _) => Some(path.clone()),
+            (None, Some(dir)) if !self.games.contains_key(id) => {
+                Some(dir.join(format!("{}.toml", id)))
+            }
+            _ => None,
+        };
+
+        match game_file {
+            Some(path) => {
+                game.write_to_path(&path)?;
+                self.games.insert(id.to_owned(), game);
+                self.game_files.insert(id.to_owned(), path);
+                Ok(())
+            }
+            None => {
+                self.games.insert(id.to_owned(), game);
+                self.write_to_path(toml_filepath)
+            }
+        }
+    }
+
+    // Removes a game from wherever it is defined. Returns the removed game,
+    // or None if there was no game with this ID.
+    pub fn remove_game(&mut self, toml_filepath: &PathBuf, id: &str) -> io::Result<Option<Game>> {
+        let game = match self.games.remove(id) {
+            Some(game) => game,
+            None => return Ok(None),
+        };
 
-        file.write_all(toml_string.as_bytes())
+        match self.game_files.remove(id) {
+            Some(path) => fs::remove_file(path)?,
+            None => self.write_to_path(toml_filepath)?,
+        }
+        Ok(Some(game))
     }
 
+    // Adds imported games and saves them. Games that conflict with one
+    // already in the config, or that don't validate, are skipped.
+    pub fn merge_games(
+        &mut self,
+        toml_filepath: &PathBuf,
+        games: Vec<(String, Game)>,
+    ) -> io::Result<MergeReport> {
+        let mut report = MergeReport::default();
+        for (id, game) in games {
+            let invalid = game.validate(&id).err().map(|err| format!("{}", err));
+            match merge_conflict(&self.games, &id, &game).or(invalid) {
+                Some(reason) => {
+                    report.skipped.insert(id, reason);
+                }
+                None => {
+                    self.save_game(toml_filepath, &id, game)?;
+                    report.added.push(id);
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    // Like merge_games, but games that validate::check_game finds problems
+    // with are skipped too. For imports that should be ready to launch.
+    pub fn merge_launchable_games(
+        &mut self,
+        toml_filepath: &PathBuf,
+        games: Vec<(String, Game)>,
+    ) -> io::Result<MergeReport> {
+        let mut unlaunchable = BTreeMap::new();
+        let games = games
+            .into_iter()
+            .filter(|&(ref id, ref game)| {
+                let problems = validate::check_game(game, &self.static_dir_path());
+                if !problems.is_empty() {
+                    unlaunchable.insert(id.clone(), problems.join("; "));
+                }
+                problems.is_empty()
+            })
+            .collect();
+
+        let mut report = self.merge_games(toml_filepath, games)?;
+        report.skipped.extend(unlaunchable);
+        Ok(report)
+    }
+
+    // Configs written for an older version are upgraded on disk first
     pub fn load(toml_filepath: &PathBuf) -> Result<Config, io::Error> {
+        migrate::upgrade(toml_filepath)?;
+
         let mut config_toml = String::new();
         File::open(&toml_filepath)?.read_to_string(&mut config_toml)?;
 
         // error casting for homogeneous errors
-        toml::from_str(&config_toml).map_err(|err| io::Error::new(ErrorKind::Other, err))
+        let mut config: Config =
+            toml::from_str(&config_toml).map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+        config.resolve_paths(toml_filepath);
+        for conflict in config.load_games_dir(toml_filepath)? {
+            println!("Skipping game: {}", conflict);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Resolves relative paths against the config file at `toml_filepath`
+    pub fn resolve_paths(&mut self, toml_filepath: &Path) {
+        self.config_dir = toml_filepath
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf();
+    }
+
+    // Where static_dir is, relative to the config file
+    pub fn static_dir_path(&self) -> PathBuf {
+        self.config_dir.join(&self.static_dir)
+    }
+
+    // Where games_dir is, relative to the config file at `toml_filepath`
+    pub fn games_dir_path(&self, toml_filepath: &Path) -> Option<PathBuf> {
+        self.games_dir.as_ref().map(|dir| {
+            toml_filepath
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(dir)
+        })
+    }
+
+    // Merges the games in games_dir into `games`. A game defined both
+    // inline and in games_dir keeps its inline definition, rather than one
+    // silently replacing the other. Files whose names aren't valid game IDs
+    // are left out too; both are returned so they can be reported.
+    pub fn load_games_dir(&mut self, toml_filepath: &Path) -> Result<Vec<String>, io::Error> {
+        let dir = match self.games_dir_path(toml_filepath) {
+            Some(dir) => dir,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut conflicts = Vec::new();
+        for path in games_dir_files(&dir)? {
+            let id = game_id(&path);
+            if !is_valid_game_id(&id) {
+                conflicts.push(format!(
+                    "{} is not named after a valid game ID",
+                    path.display()
+                ));
+                continue;
+            }
+            if self.games.contains_key(&id) {
+                conflicts.push(format!(
+                    "game `{}` is defined in both the config and {}",
+                    id,
+                    path.display()
+                ));
+                continue;
+            }
+            let game = Game::load(&path)?;
+            self.games.insert(id.clone(), game);
+            self.game_files.insert(id, path);
+        }
+        Ok(conflicts)
+    }
+
+    // Rejects configs that would only fail once a game is launched
+    pub fn validate(&self) -> Result<(), io::Error> {
+        self.auth.validate()?;
+        for hook in self.pre_launch.iter().chain(&self.post_exit) {
+            check_args("hook args".to_owned(), &hook.args)?;
+        }
+        for (id, game) in &self.games {
+            game.validate(id)?;
+        }
+        Ok(())
     }
 }
 
+fn check_args(key: String, args: &[String]) -> Result<(), io::Error> {
+    for arg in args {
+        check_template(arg)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", key, err)))?;
+    }
+    Ok(())
+}
+
+// Game IDs are used as TOML keys, file names and URL path segments
+pub fn is_valid_game_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// A game ID made from a game's name, such as `guilty_gear_xrd_rev_2` for
+// "GUILTY GEAR Xrd -REV 2-". Empty when the name has no ASCII letters or
+// digits.
+pub fn suggested_game_id(name: &str) -> String {
+    let mut id = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            id.push(c.to_ascii_lowercase());
+        } else if !id.is_empty() && !id.ends_with('_') {
+            id.push('_');
+        }
+    }
+    id.trim_end_matches('_').to_owned()
+}
+
+// The result of merging imported games into the config
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct MergeReport {
+    pub added: Vec<String>,
+    // Game ID and why it wasn't added
+    pub skipped: BTreeMap<String, String>,
+}
+
+// Why an imported game can't be added to `games`, if it can't
+pub fn merge_conflict(games: &HashMap<String, Game>, id: &str, game: &Game) -> Option<String> {
+    let imported = games.iter().find(|&(_, existing)| {
+        existing.exe_path != Path::new("") && existing.exe_path == game.exe_path
+    });
+    if let Some((existing_id, _)) = imported {
+        Some(format!("already imported as {}", existing_id))
+    } else if games.contains_key(id) {
+        Some("a game with this ID already exists".to_owned())
+    } else {
+        None
+    }
+}
+
+// Writes to a temporary file next to `path` and renames it over `path`, so
+// a crash part way through leaves either the old or the new file behind.
+// The file keeps its permissions; new files are only readable by the owner.
+pub fn write_atomically(path: &Path, contents: &str) -> Result<(), io::Error> {
+    replace_file(path, contents, false)
+}
+
+// Like write_atomically, but only the owner can read or write the file, for
+// files holding password hashes
+pub fn write_private(path: &Path, contents: &str) -> Result<(), io::Error> {
+    replace_file(path, contents, true)
+}
+
+fn replace_file(path: &Path, contents: &str, private: bool) -> Result<(), io::Error> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, format!("{:?} is not a file", path)))?;
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    let result = create_temp_file(path, &temp_path, private).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    match result.and_then(|_| fs::rename(&temp_path, path)) {
+        // The rename itself only lasts once the directory is synced too
+        Ok(()) => sync_dir(path.parent().unwrap_or_else(|| Path::new(""))),
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(err)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_temp_file(path: &Path, temp_path: &Path, private: bool) -> Result<File, io::Error> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mode = match fs::metadata(path) {
+        Ok(ref metadata) if !private => metadata.permissions().mode() & 0o7777,
+        _ => 0o600,
+    };
+    // A temp file left over from a crash keeps its mode, so start afresh
+    let _ = fs::remove_file(temp_path);
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(temp_path)?;
+    // The umask may have taken some of the bits away
+    file.set_permissions(Permissions::from_mode(mode))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_temp_file(_path: &Path, temp_path: &Path, _private: bool) -> Result<File, io::Error> {
+    File::create(temp_path)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<(), io::Error> {
+    let dir = if dir == Path::new("") { Path::new(".") } else { dir };
+    File::open(dir)?.sync_all()
+}
+
+// Directories can't be opened as files on Windows
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<(), io::Error> {
+    Ok(())
+}
+
 // using PartialEq for unit tests
 // Using clone in a unit test atm.  Might not be necessary
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
 pub struct Game {
     pub name: String,
     pub description: String,
-    pub genres: Vec<String>,
+    pub tags: Vec<String>,
     pub thumbnail_path: PathBuf,
+    // Catalog details for the UI, also used to filter list_games. A game
+    // without player counts is a single player game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_players: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_players: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub play_modes: Vec<PlayMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_year: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub developer: Option<String>,
+    // The controls the game can be played with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<Input>,
+    // Free form, such as "PEGI 12" or "ESRB T"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age_rating: Option<String>,
+    // Position in the default listing. Games without one come after those
+    // with one, by name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<i64>,
     pub exe_path: PathBuf,
     pub exe_args: Vec<String>,
+    // Defaults to the directory containing exe_path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<PathBuf>,
+    // Start the game with only the variables in `env` instead of
+    // inheriting the server's environment
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub env_clear: bool,
+    // Overrides the max_session_minutes from the top level of the config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_session_minutes: Option<u64>,
+    // Extra environment variables set for the game process
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    // When unset, the hooks from the top level of the config are used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_launch: Option<Vec<Hook>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_exit: Option<Vec<Hook>>,
+}
+
+// How several players play a game together
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    Versus,
+    Coop,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    Stick,
+    Pad,
+    LightGun,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Auth {
+    // Set with the set_password binary. Without one, nobody can log in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    // A login ends after this long without a request...
+    #[serde(default = "default_session_idle_minutes")]
+    pub session_idle_minutes: u64,
+    // ...or this long after logging in, whichever comes first
+    #[serde(default = "default_session_max_hours")]
+    pub session_max_hours: u64,
+    // Only send the session cookie over HTTPS. Set this when the server is
+    // behind a TLS proxy.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub secure_cookie: bool,
+    // Wrong passwords from one address before it is locked out...
+    #[serde(default = "default_max_failed_logins")]
+    pub max_failed_logins: u32,
+    // ...for this long
+    #[serde(default = "default_lockout_minutes")]
+    pub lockout_minutes: u64,
+    // Failed and refused logins are appended here
+    #[serde(default = "default_audit_log_path")]
+    pub audit_log_path: PathBuf,
+    // How new password hashes are made. Stored hashes that are weaker are
+    // replaced at the next login.
+    #[serde(default)]
+    pub password_scheme: Scheme,
+    #[serde(default = "default_bcrypt_cost")]
+    pub bcrypt_cost: u32,
+    #[serde(default = "default_argon2_memory_kib")]
+    pub argon2_memory_kib: u32,
+    #[serde(default = "default_argon2_iterations")]
+    pub argon2_iterations: u32,
+}
+
+fn default_session_idle_minutes() -> u64 {
+    120
+}
+
+fn default_session_max_hours() -> u64 {
+    12
+}
+
+fn default_max_failed_logins() -> u32 {
+    10
+}
+
+fn default_lockout_minutes() -> u64 {
+    15
+}
+
+fn default_audit_log_path() -> PathBuf {
+    PathBuf::from("login_audit.jsonl")
+}
+
+fn default_bcrypt_cost() -> u32 {
+    HashPolicy::default().bcrypt_cost
+}
+
+fn default_argon2_memory_kib() -> u32 {
+    HashPolicy::default().argon2_memory_kib
+}
+
+fn default_argon2_iterations() -> u32 {
+    HashPolicy::default().argon2_iterations
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Auth {
+            password_hash: None,
+            session_idle_minutes: default_session_idle_minutes(),
+            session_max_hours: default_session_max_hours(),
+            secure_cookie: false,
+            max_failed_logins: default_max_failed_logins(),
+            lockout_minutes: default_lockout_minutes(),
+            audit_log_path: default_audit_log_path(),
+            password_scheme: Scheme::default(),
+            bcrypt_cost: default_bcrypt_cost(),
+            argon2_memory_kib: default_argon2_memory_kib(),
+            argon2_iterations: default_argon2_iterations(),
+        }
+    }
+}
+
+impl Auth {
+    pub fn session_policy(&self) -> SessionPolicy {
+        SessionPolicy {
+            idle_secs: self.session_idle_minutes.saturating_mul(60),
+            max_age_secs: self.session_max_hours.saturating_mul(60 * 60),
+        }
+    }
+
+    pub fn hash_policy(&self) -> HashPolicy {
+        HashPolicy {
+            scheme: self.password_scheme,
+            bcrypt_cost: self.bcrypt_cost,
+            argon2_memory_kib: self.argon2_memory_kib,
+            argon2_iterations: self.argon2_iterations,
+        }
+    }
+
+    pub fn throttle_policy(&self) -> ThrottlePolicy {
+        ThrottlePolicy {
+            lockout_after: self.max_failed_logins,
+            lockout_secs: self.lockout_minutes.saturating_mul(60),
+            ..ThrottlePolicy::default()
+        }
+    }
+
+    // Rejects work factors that hashing would fail with, so a login isn't
+    // the first place a bad [auth] section shows up
+    pub fn validate(&self) -> Result<(), io::Error> {
+        let invalid = |key: &str, message: String| {
+            Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("auth.{}: {}", key, message),
+            ))
+        };
+        if !(4..=31).contains(&self.bcrypt_cost) {
+            return invalid(
+                "bcrypt_cost",
+                format!("must be between 4 and 31, got {}", self.bcrypt_cost),
+            );
+        }
+        if self.argon2_memory_kib < 8 {
+            return invalid(
+                "argon2_memory_kib",
+                format!("must be at least 8, got {}", self.argon2_memory_kib),
+            );
+        }
+        if self.argon2_iterations < 1 {
+            return invalid("argon2_iterations", "must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+// A command run around a game, in the game's working directory and
+// environment. Args accept the same placeholders as exe_args.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct Hook {
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+impl Game {
+    pub fn load(path: &Path) -> Result<Game, io::Error> {
+        let mut game_toml = String::new();
+        File::open(path)?.read_to_string(&mut game_toml)?;
+
+        toml::from_str(&game_toml).map_err(|err| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), err),
+            )
+        })
+    }
+
+    pub fn write_to_path(&self, path: &Path) -> Result<(), io::Error> {
+        let toml_string = document::to_toml_string(path, self)?;
+        write_atomically(path, &toml_string)
+    }
+
+    // Rejects games that would only fail once they are launched
+    pub fn validate(&self, id: &str) -> Result<(), io::Error> {
+        check_args(format!("games.{}.exe_args", id), &self.exe_args)?;
+        let min_players = self.min_players.unwrap_or(1);
+        if min_players == 0 || self.max_players.map_or(false, |max| max < min_players) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "games.{}: min_players must be at least 1 and no more than max_players",
+                    id
+                ),
+            ));
+        }
+        for hook in self.pre_launch.iter().chain(&self.post_exit).flatten() {
+            check_args(format!("games.{} hook args", id), &hook.args)?;
+        }
+        Ok(())
+    }
+}
+
+// The `*.toml` files in a games_dir, sorted by name
+pub fn games_dir_files(dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().map_or(false, |ext| ext == "toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+// Drop-in game files are named after the game they hold
+pub fn game_id(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
@@ -0,0 +1,165 @@
+// Saves config values by editing the existing TOML document in place.
+//
+// Both the value currently in the file and the new value are serialized,
+// and only the keys that differ between the two are changed in the
+// document. Comments, key order, table order and the way untouched values
+// are written all survive the edit.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+use toml;
+use toml_edit::{ArrayOfTables, Document, Item, Table, Value};
+
+fn to_toml<T: Serialize>(value: &T) -> Result<toml::Value, io::Error> {
+    toml::Value::try_from(value).map_err(|err| {
+        io::Error::new(
+            ErrorKind::Other,
+            format!("An error occured when serializing toml: {}", err),
+        )
+    })
+}
+
+fn is_array_of_tables(array: &[toml::Value]) -> bool {
+    !array.is_empty() && array.iter().all(|value| value.is_table())
+}
+
+fn to_item(value: &toml::Value) -> Item {
+    match *value {
+        toml::Value::Table(ref table) => {
+            let mut item = Table::new();
+            for (key, value) in table {
+                item.insert(key, to_item(value));
+            }
+            Item::Table(item)
+        }
+        toml::Value::Array(ref array) if is_array_of_tables(array) => {
+            let mut tables = ArrayOfTables::new();
+            for value in array {
+                if let Item::Table(table) = to_item(value) {
+                    tables.push(table);
+                }
+            }
+            Item::ArrayOfTables(tables)
+        }
+        _ => Item::Value(to_value(value)),
+    }
+}
+
+fn to_value(value: &toml::Value) -> Value {
+    match *value {
+        toml::Value::String(ref string) => Value::from(string.as_str()),
+        toml::Value::Integer(integer) => Value::from(integer),
+        toml::Value::Float(float) => Value::from(float),
+        toml::Value::Boolean(boolean) => Value::from(boolean),
+        toml::Value::Datetime(ref datetime) => datetime
+            .to_string()
+            .parse()
+            .unwrap_or_else(|_| Value::from(datetime.to_string())),
+        toml::Value::Array(ref array) => array.iter().map(to_value).collect(),
+        toml::Value::Table(ref table) => table
+            .iter()
+            .map(|(key, value)| (key.as_str(), to_value(value)))
+            .collect(),
+    }
+}
+
+// Changes `item` from `old` to `new`, touching as little of it as possible
+fn apply(item: &mut Item, old: &toml::Value, new: &toml::Value) {
+    if old == new {
+        return;
+    }
+
+    if let (Some(old), Some(new)) = (old.as_table(), new.as_table()) {
+        // Inline tables can only hold values
+        let inline = item.is_inline_table();
+        if let Some(table) = item.as_table_like_mut() {
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                table.remove(key);
+            }
+            for (key, new) in new {
+                // Defaults that were left out of the document stay out
+                if old.get(key) == Some(new) {
+                    continue;
+                }
+                match (old.get(key), table.get_mut(key)) {
+                    (Some(old), Some(item)) => apply(item, old, new),
+                    _ => {
+                        let item = if inline {
+                            Item::Value(to_value(new))
+                        } else {
+                            to_item(new)
+                        };
+                        table.insert(key, item);
+                    }
+                }
+            }
+            return;
+        }
+    }
+
+    let replacement = match *item {
+        Item::Value(ref old) => {
+            // Keep the whitespace and comments around the old value
+            let mut value = to_value(new);
+            *value.decor_mut() = old.decor().clone();
+            Item::Value(value)
+        }
+        _ => to_item(new),
+    };
+    *item = replacement;
+}
+
+// The document `source` edited so that it holds `value`. Returns None when
+// `source` can't be edited in place, for example because it doesn't parse.
+pub fn edit_source<T>(source: &str, value: &T) -> Result<Option<String>, io::Error>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut document: Document = match source.parse() {
+        Ok(document) => document,
+        Err(_) => return Ok(None),
+    };
+    let old = match toml::from_str::<T>(source) {
+        Ok(old) => to_toml(&old)?,
+        Err(_) => return Ok(None),
+    };
+    let new = to_toml(value)?;
+
+    apply(document.as_item_mut(), &old, &new);
+    let edited = document.to_string();
+
+    // Anything the edit couldn't express exactly falls back to a rewrite
+    match toml::from_str::<T>(&edited) {
+        Ok(ref reparsed) if to_toml(reparsed)? == new => Ok(Some(edited)),
+        _ => Ok(None),
+    }
+}
+
+// The contents `value` should be saved as when `path` holds it: the current
+// file edited in place when possible, otherwise `value` serialized in full
+pub fn to_toml_string<T>(path: &Path, value: &T) -> Result<String, io::Error>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut source = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.read_to_string(&mut source)?;
+        }
+        Err(ref err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    if let Some(edited) = edit_source(&source, value)? {
+        return Ok(edited);
+    }
+    toml::to_string(value).map_err(|err| {
+        io::Error::new(
+            ErrorKind::Other,
+            format!("An error occured when serializing toml: {}", err),
+        )
+    })
+}
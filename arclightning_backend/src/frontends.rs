@@ -0,0 +1,525 @@
+// Converts games to and from the XML files of other front-ends:
+// EmulationStation's gamelist.xml and LaunchBox's platform files
+// (LaunchBox/Data/Platforms/<platform>.xml).
+//
+// Only part of a game maps onto either format. Whatever doesn't, in either
+// direction, is listed in an `Unmapped` report instead of being dropped
+// silently.
+
+use config::{suggested_game_id, Game};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use xmltree::{Element, EmitterConfig, XMLNode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    EmulationStation,
+    LaunchBox,
+}
+
+// Element names of the fields that map onto a Game
+struct Fields {
+    root: &'static str,
+    game: &'static str,
+    name: &'static str,
+    description: &'static str,
+    genre: &'static str,
+    image: Option<&'static str>,
+    path: &'static str,
+    args: Option<&'static str>,
+    developer: &'static str,
+    release_date: &'static str,
+    // "1-2" in EmulationStation, while LaunchBox only has a maximum
+    players: Option<&'static str>,
+    max_players: Option<&'static str>,
+    age_rating: Option<&'static str>,
+    // How several genres are written in the one genre element
+    genre_separators: &'static [char],
+    genre_join: &'static str,
+}
+
+const EMULATIONSTATION: Fields = Fields {
+    root: "gameList",
+    game: "game",
+    name: "name",
+    description: "desc",
+    genre: "genre",
+    image: Some("image"),
+    path: "path",
+    args: None,
+    developer: "developer",
+    release_date: "releasedate",
+    players: Some("players"),
+    max_players: None,
+    age_rating: None,
+    genre_separators: &[',', '/'],
+    genre_join: ", ",
+};
+
+const LAUNCHBOX: Fields = Fields {
+    root: "LaunchBox",
+    game: "Game",
+    name: "Title",
+    description: "Notes",
+    genre: "Genre",
+    image: None,
+    path: "ApplicationPath",
+    args: Some("CommandLine"),
+    developer: "Developer",
+    release_date: "ReleaseDate",
+    players: None,
+    max_players: Some("MaxPlayers"),
+    age_rating: Some("Rating"),
+    genre_separators: &[';'],
+    genre_join: "; ",
+};
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.to_ascii_lowercase().as_str() {
+            "emulationstation" | "es" => Some(Format::EmulationStation),
+            "launchbox" => Some(Format::LaunchBox),
+            _ => None,
+        }
+    }
+
+    // The format of a parsed file, from its root element
+    pub fn detect(root: &Element) -> Option<Format> {
+        [Format::EmulationStation, Format::LaunchBox]
+            .iter()
+            .cloned()
+            .find(|format| format.fields().root == root.name)
+    }
+
+    fn fields(self) -> &'static Fields {
+        match self {
+            Format::EmulationStation => &EMULATIONSTATION,
+            Format::LaunchBox => &LAUNCHBOX,
+        }
+    }
+
+    // Relative paths in gamelist.xml are relative to the file itself, and
+    // in LaunchBox to the LaunchBox directory two levels up
+    fn base_dir(self, xml_path: &Path) -> PathBuf {
+        let dir = xml_path.parent().unwrap_or_else(|| Path::new(""));
+        match self {
+            Format::EmulationStation => dir.to_path_buf(),
+            Format::LaunchBox => dir
+                .parent()
+                .and_then(Path::parent)
+                .unwrap_or(dir)
+                .to_path_buf(),
+        }
+    }
+
+    // Only the year is kept, so dates are written as January 1st
+    fn release_date(self, year: u32) -> String {
+        match self {
+            Format::EmulationStation => format!("{:04}0101T000000", year),
+            Format::LaunchBox => format!("{:04}-01-01T00:00:00", year),
+        }
+    }
+}
+
+// Something that was left out of a conversion
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Unmapped {
+    // None for elements outside of any game
+    pub game: Option<String>,
+    pub field: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Import {
+    // Game IDs are made from the game names, in the order of the file
+    pub games: Vec<(String, Game)>,
+    pub unmapped: Vec<Unmapped>,
+}
+
+fn invalid(path: &Path, message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+}
+
+// `path` made absolute, with . and .. worked out, so paths written in
+// different ways can be compared
+fn absolute(path: &Path) -> PathBuf {
+    let joined = if path.is_relative() {
+        env::current_dir().unwrap_or_default().join(path)
+    } else {
+        path.to_path_buf()
+    };
+    let mut absolute = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component.as_os_str()),
+        }
+    }
+    absolute
+}
+
+fn child_elements(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(XMLNode::as_element)
+}
+
+fn text(element: &Element) -> String {
+    element
+        .get_text()
+        .map(|text| text.trim().to_owned())
+        .unwrap_or_default()
+}
+
+// Splits a command line into arguments, keeping "quoted arguments" whole.
+// Braces are escaped, since they would be placeholders in exe_args.
+fn split_command_line(command_line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = None;
+    let mut quoted = false;
+    for c in command_line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => args.extend(arg.take()),
+            '{' => arg.get_or_insert_with(String::new).push_str("{{"),
+            '}' => arg.get_or_insert_with(String::new).push_str("}}"),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+// The reverse of split_command_line, unescaping braces
+fn join_command_line(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let arg = arg.replace("{{", "{").replace("}}", "}");
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("\"{}\"", arg)
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Both formats start dates with the year
+fn release_year(date: &str) -> Option<u32> {
+    date.get(..4).and_then(|year| year.parse().ok())
+}
+
+// "2", or "1-2" for one to two players
+fn parse_players(players: &str) -> Option<(u32, u32)> {
+    let mut range = players.splitn(2, '-').map(|n| n.trim().parse::<u32>());
+    match (range.next(), range.next()) {
+        (Some(Ok(max)), None) => Some((1, max)),
+        (Some(Ok(min)), Some(Ok(max))) if min <= max => Some((min, max)),
+        _ => None,
+    }
+}
+
+// `id`, or `id_2`, `id_3`... if it is already taken
+fn unique_id(taken: &mut HashSet<String>, id: String) -> String {
+    let mut unique = id.clone();
+    let mut n = 1;
+    while taken.contains(&unique) {
+        n += 1;
+        unique = format!("{}_{}", id, n);
+    }
+    taken.insert(unique.clone());
+    unique
+}
+
+fn import_game(
+    format: Format,
+    base_dir: &Path,
+    static_dir: &Path,
+    element: &Element,
+    taken: &mut HashSet<String>,
+    unmapped: &mut Vec<Unmapped>,
+) -> (String, Game) {
+    let fields = format.fields();
+    let mut game = Game::default();
+    let mut others = Vec::new();
+
+    for child in child_elements(element) {
+        let value = text(child);
+        let name = child.name.as_str();
+        if name == fields.name {
+            game.name = value;
+        } else if name == fields.description {
+            game.description = value;
+        } else if name == fields.genre {
+            game.tags = value
+                .split(fields.genre_separators)
+                .map(str::trim)
+                .filter(|genre| !genre.is_empty())
+                .map(str::to_owned)
+                .collect();
+        } else if Some(name) == fields.image && !value.is_empty() {
+            // Images are relative to the XML file, thumbnails to static_dir
+            let image = absolute(&base_dir.join(&value));
+            match image.strip_prefix(absolute(static_dir)) {
+                Ok(thumbnail) => game.thumbnail_path = thumbnail.to_path_buf(),
+                Err(_) => others.push((name.to_owned(), value)),
+            }
+        } else if name == fields.path {
+            let path = PathBuf::from(&value);
+            game.exe_path = if path.is_relative() && !value.starts_with('~') {
+                base_dir.join(path)
+            } else {
+                path
+            };
+        } else if Some(name) == fields.args {
+            game.exe_args = split_command_line(&value);
+        } else if name == fields.developer && !value.is_empty() {
+            game.developer = Some(value);
+        } else if name == fields.release_date && release_year(&value).is_some() {
+            game.release_year = release_year(&value);
+        } else if Some(name) == fields.players && parse_players(&value).is_some() {
+            let (min, max) = parse_players(&value).unwrap_or((1, 1));
+            game.min_players = Some(min);
+            game.max_players = Some(max);
+        } else if Some(name) == fields.max_players && value.parse::<u32>().is_ok() {
+            game.max_players = value.parse().ok();
+        } else if Some(name) == fields.age_rating && !value.is_empty() {
+            game.age_rating = Some(value);
+        } else if !value.is_empty() {
+            others.push((name.to_owned(), value));
+        }
+    }
+    for (name, value) in &element.attributes {
+        others.push((format!("@{}", name), value.clone()));
+    }
+
+    if game.name.is_empty() {
+        game.name = game
+            .exe_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+    let mut id = suggested_game_id(&game.name);
+    if id.is_empty() {
+        id = "game".to_owned();
+    }
+    let id = unique_id(taken, id);
+
+    others.sort();
+    unmapped.extend(others.into_iter().map(|(field, value)| Unmapped {
+        game: Some(id.clone()),
+        field,
+        value,
+    }));
+    (id, game)
+}
+
+// Reads the games from a gamelist.xml or LaunchBox platform file. The
+// format is detected from the file when `format` is None. Images outside of
+// `static_dir` can't be thumbnails and are left unmapped.
+pub fn import(path: &Path, format: Option<Format>, static_dir: &Path) -> Result<Import, io::Error> {
+    let root = Element::parse(File::open(path)?).map_err(|err| invalid(path, err.to_string()))?;
+    let format = match format.or_else(|| Format::detect(&root)) {
+        Some(format) => format,
+        None => {
+            return Err(invalid(
+                path,
+                format!("<{}> is not a gamelist.xml or LaunchBox platform file", root.name),
+            ))
+        }
+    };
+    let fields = format.fields();
+    if root.name != fields.root {
+        return Err(invalid(
+            path,
+            format!("expected a <{}> element, found <{}>", fields.root, root.name),
+        ));
+    }
+
+    let base_dir = format.base_dir(path);
+    let mut import = Import::default();
+    let mut taken = HashSet::new();
+    for element in child_elements(&root) {
+        if element.name == fields.game {
+            let game = import_game(
+                format,
+                &base_dir,
+                static_dir,
+                element,
+                &mut taken,
+                &mut import.unmapped,
+            );
+            import.games.push(game);
+        } else {
+            // Folders, platforms, additional applications and the like
+            let name = element
+                .get_child(fields.name)
+                .or_else(|| element.get_child("Name"))
+                .map(text)
+                .unwrap_or_default();
+            import.unmapped.push(Unmapped {
+                game: None,
+                field: element.name.clone(),
+                value: name,
+            });
+        }
+    }
+    Ok(import)
+}
+
+fn add_child(parent: &mut Element, name: &str, value: String) {
+    let mut child = Element::new(name);
+    child.children.push(XMLNode::Text(value));
+    parent.children.push(XMLNode::Element(child));
+}
+
+// Settings of `game` the format has no place for
+fn unmapped_fields(format: Format, id: &str, game: &Game) -> Vec<Unmapped> {
+    let fields = format.fields();
+    let mut unmapped = Vec::new();
+    {
+        let mut add = |field: &str, value: String| {
+            unmapped.push(Unmapped {
+                game: Some(id.to_owned()),
+                field: field.to_owned(),
+                value,
+            })
+        };
+        if fields.args.is_none() && !game.exe_args.is_empty() {
+            add("exe_args", game.exe_args.join(" "));
+        }
+        if fields.image.is_none() && game.thumbnail_path != Path::new("") {
+            add("thumbnail_path", game.thumbnail_path.display().to_string());
+        }
+        if fields.players.is_none() && game.min_players.map_or(false, |min| min > 1) {
+            add("min_players", game.min_players.unwrap_or(1).to_string());
+        }
+        if !game.play_modes.is_empty() {
+            add("play_modes", format!("{:?}", game.play_modes));
+        }
+        if !game.inputs.is_empty() {
+            add("inputs", format!("{:?}", game.inputs));
+        }
+        if let (None, Some(ref rating)) = (fields.age_rating, &game.age_rating) {
+            add("age_rating", rating.clone());
+        }
+        if let Some(order) = game.sort_order {
+            add("sort_order", order.to_string());
+        }
+        if let Some(ref working_dir) = game.working_dir {
+            add("working_dir", working_dir.display().to_string());
+        }
+        if game.env_clear {
+            add("env_clear", "true".to_owned());
+        }
+        if let Some(minutes) = game.max_session_minutes {
+            add("max_session_minutes", minutes.to_string());
+        }
+        let mut env: Vec<String> = game
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        env.sort();
+        if !env.is_empty() {
+            add("env", env.join(" "));
+        }
+        if let Some(ref hooks) = game.pre_launch {
+            add("pre_launch", format!("{} hooks", hooks.len()));
+        }
+        if let Some(ref hooks) = game.post_exit {
+            add("post_exit", format!("{} hooks", hooks.len()));
+        }
+    }
+    unmapped
+}
+
+// Writes `games` in `format` for the file at `path`, sorted by game ID.
+// Thumbnails in `static_dir` are written relative to `path` where they can
+// be. Returns the XML and the settings that had to be left out.
+pub fn export(
+    format: Format,
+    games: &HashMap<String, Game>,
+    path: &Path,
+    static_dir: &Path,
+) -> Result<(String, Vec<Unmapped>), io::Error> {
+    let fields = format.fields();
+    let base_dir = absolute(&format.base_dir(path));
+    let mut ids: Vec<&String> = games.keys().collect();
+    ids.sort();
+
+    let mut root = Element::new(fields.root);
+    let mut unmapped = Vec::new();
+    for id in ids {
+        let game = &games[id];
+        let mut element = Element::new(fields.game);
+        add_child(&mut element, fields.path, game.exe_path.display().to_string());
+        add_child(&mut element, fields.name, game.name.clone());
+        if !game.description.is_empty() {
+            add_child(&mut element, fields.description, game.description.clone());
+        }
+        if !game.tags.is_empty() {
+            add_child(&mut element, fields.genre, game.tags.join(fields.genre_join));
+        }
+        if let Some(image) = fields.image {
+            if game.thumbnail_path != Path::new("") {
+                let thumbnail = absolute(&static_dir.join(&game.thumbnail_path));
+                let value = match thumbnail.strip_prefix(&base_dir) {
+                    Ok(relative) => Path::new(".").join(relative),
+                    Err(_) => thumbnail.clone(),
+                };
+                add_child(&mut element, image, value.display().to_string());
+            }
+        }
+        if let Some(args) = fields.args {
+            if !game.exe_args.is_empty() {
+                add_child(&mut element, args, join_command_line(&game.exe_args));
+            }
+        }
+        if let Some(ref developer) = game.developer {
+            add_child(&mut element, fields.developer, developer.clone());
+        }
+        if let Some(year) = game.release_year {
+            add_child(&mut element, fields.release_date, format.release_date(year));
+        }
+        let min_players = game.min_players.unwrap_or(1);
+        if let (Some(players), Some(max)) = (fields.players, game.max_players) {
+            let players_text = if min_players == max {
+                max.to_string()
+            } else {
+                format!("{}-{}", min_players, max)
+            };
+            add_child(&mut element, players, players_text);
+        }
+        if let (Some(max_players), Some(max)) = (fields.max_players, game.max_players) {
+            add_child(&mut element, max_players, max.to_string());
+        }
+        if let (Some(age_rating), Some(ref rating)) = (fields.age_rating, &game.age_rating) {
+            add_child(&mut element, age_rating, rating.clone());
+        }
+        root.children.push(XMLNode::Element(element));
+        unmapped.extend(unmapped_fields(format, id, game));
+    }
+
+    let mut xml = Vec::new();
+    root.write_with_config(&mut xml, EmitterConfig::new().perform_indent(true))
+        .map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("An error occured when writing XML: {}", err),
+            )
+        })?;
+    let mut xml = String::from_utf8(xml).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+    xml.push('\n');
+    Ok((xml, unmapped))
+}
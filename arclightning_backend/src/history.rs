@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::path::PathBuf;
+
+// One finished play session, stored as a line of JSON in the history file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlayRecord {
+    pub game_id: String,
+    pub started: u64,
+    pub ended: u64,
+    pub duration_secs: u64,
+    pub code: Option<i32>,
+    #[serde(default)]
+    pub players: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GameStats {
+    pub game_id: String,
+    pub plays: u64,
+    pub total_minutes: f64,
+}
+
+// Append-only log of every game played on the cabinet
+#[derive(Debug, Clone)]
+pub struct PlayHistory {
+    path: PathBuf,
+}
+
+impl PlayHistory {
+    pub fn new(path: PathBuf) -> Self {
+        PlayHistory { path }
+    }
+
+    pub fn append(&self, record: &PlayRecord) -> Result<(), io::Error> {
+        let json =
+            serde_json::to_string(record).map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", json)
+    }
+
+    // Plays that started in [from, to), oldest first
+    pub fn records(&self, from: u64, to: u64) -> Result<Vec<PlayRecord>, io::Error> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            // A line cut short by a crash shouldn't hide the rest of the history
+            match serde_json::from_str::<PlayRecord>(&line) {
+                Ok(record) => {
+                    if record.started >= from && record.started < to {
+                        records.push(record);
+                    }
+                }
+                Err(err) => println!("Skipping bad play history line: {}", err),
+            }
+        }
+        Ok(records)
+    }
+
+    // Plays and minutes per game for plays that started in [from, to)
+    pub fn stats(&self, from: u64, to: u64) -> Result<Vec<GameStats>, io::Error> {
+        let mut totals: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for record in self.records(from, to)? {
+            let total = totals.entry(record.game_id).or_insert((0, 0));
+            total.0 += 1;
+            total.1 += record.duration_secs;
+        }
+
+        Ok(totals
+            .into_iter()
+            .map(|(game_id, (plays, secs))| GameStats {
+                game_id,
+                plays,
+                total_minutes: secs as f64 / 60.0,
+            })
+            .collect())
+    }
+}
+
+pub fn stats_to_csv(stats: &[GameStats]) -> String {
+    let mut csv = "game_id,plays,total_minutes\n".to_owned();
+    for game in stats {
+        // Game IDs are TOML keys, so quote them in case they contain commas
+        csv.push_str(&format!(
+            "\"{}\",{},{:.1}\n",
+            game.game_id.replace('"', "\"\""),
+            game.plays,
+            game.total_minutes
+        ));
+    }
+    csv
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Seconds from the epoch to midnight UTC of a YYYY-MM-DD date
+fn parse_date(value: &str) -> Option<u64> {
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let year = parts[0].parse::<i64>().ok()?;
+    let month = parts[1].parse::<i64>().ok()?;
+    let day = parts[2].parse::<i64>().ok()?;
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let year_of_era = y - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    if days < 0 {
+        None
+    } else {
+        Some(days as u64 * 86_400)
+    }
+}
+
+// Parses a unix timestamp or a YYYY-MM-DD date (midnight UTC)
+pub fn parse_time(value: &str) -> Option<u64> {
+    match value.parse::<u64>() {
+        Ok(secs) => Some(secs),
+        Err(_) => parse_date(value),
+    }
+}
+
+// Like parse_time, for the end of a range: a date means the end of that
+// day, so `to=2018-10-18` includes plays on the 18th
+pub fn parse_end_time(value: &str) -> Option<u64> {
+    match value.parse::<u64>() {
+        Ok(secs) => Some(secs),
+        Err(_) => parse_date(value).map(|midnight| midnight + 86_400),
+    }
+}
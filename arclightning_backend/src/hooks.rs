@@ -0,0 +1,143 @@
+use process;
+use std::io::{self, ErrorKind, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Used when a hook doesn't set timeout_secs
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// How long output is collected after a hook exits. Something it left
+// running in the background can keep its pipes open for good.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+// A command run before a game starts or after it exits
+#[derive(Debug)]
+pub struct HookCommand {
+    pub command: Command,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HookOutput {
+    pub command: String,
+    pub code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookOutput {
+    pub fn success(&self) -> bool {
+        !self.timed_out && self.code == Some(0)
+    }
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut pipe) = pipe {
+            let mut bytes = Vec::new();
+            if pipe.read_to_end(&mut bytes).is_ok() {
+                output = String::from_utf8_lossy(&bytes).into_owned();
+            }
+        }
+        let _ = sender.send(output);
+    });
+    receiver
+}
+
+// The output read from a pipe, or nothing if it is still open at `deadline`
+fn collect(pipe: &Receiver<String>, deadline: Instant) -> String {
+    let left = deadline.saturating_duration_since(Instant::now());
+    pipe.recv_timeout(left).unwrap_or_default()
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<Option<i32>, io::Error> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status.code());
+        }
+        if started.elapsed() >= timeout {
+            // Whatever the hook started goes too
+            process::kill_group(child)?;
+            return Err(io::Error::new(ErrorKind::TimedOut, "hook timed out"));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+impl HookCommand {
+    // Runs the hook to completion, killing it once the timeout elapses
+    pub fn run(&mut self) -> Result<HookOutput, io::Error> {
+        let description = format!("{:?}", self.command);
+        process::new_process_group(&mut self.command);
+        let mut child = self
+            .command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Drain the pipes on their own threads so a chatty hook can't fill
+        // the pipe buffer and block until the timeout
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+
+        let (code, timed_out) = match wait_with_timeout(&mut child, self.timeout) {
+            Ok(code) => (code, false),
+            Err(ref err) if err.kind() == ErrorKind::TimedOut => (None, true),
+            Err(err) => return Err(err),
+        };
+
+        let drained_by = Instant::now() + DRAIN_TIMEOUT;
+        Ok(HookOutput {
+            command: description,
+            code,
+            timed_out,
+            stdout: collect(&stdout, drained_by),
+            stderr: collect(&stderr, drained_by),
+        })
+    }
+}
+
+// Runs hooks in order, stopping at the first one that fails
+pub fn run_all(hooks: &mut [HookCommand]) -> Result<Vec<HookOutput>, io::Error> {
+    let mut outputs = Vec::new();
+    for hook in hooks.iter_mut() {
+        let output = hook.run()?;
+        print_output(&output);
+        if !output.success() {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "{} {}: {}",
+                    output.command,
+                    if output.timed_out {
+                        "timed out".to_owned()
+                    } else {
+                        format!("exited with {:?}", output.code)
+                    },
+                    output.stderr.trim()
+                ),
+            ));
+        }
+        outputs.push(output);
+    }
+    Ok(outputs)
+}
+
+fn print_output(output: &HookOutput) {
+    println!("Hook {} finished: {:?}", output.command, output.code);
+    for line in output.stdout.lines() {
+        println!("  stdout: {}", line);
+    }
+    for line in output.stderr.lines() {
+        println!("  stderr: {}", line);
+    }
+}
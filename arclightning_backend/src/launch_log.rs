@@ -0,0 +1,120 @@
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// One game launch, stored as <id>.json next to the game's output in <id>.log
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LaunchRecord {
+    pub id: u64,
+    pub game_id: String,
+    pub started: u64,
+    pub ended: Option<u64>,
+    pub code: Option<i32>,
+    // Set when the game could not be started at all
+    pub error: Option<String>,
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() * 1000 + u64::from(time.subsec_millis()))
+        .unwrap_or(0)
+}
+
+// Directory of per-launch output logs, keeping only the newest `max_logs`
+#[derive(Debug, Clone)]
+pub struct LaunchLog {
+    dir: PathBuf,
+    max_logs: usize,
+}
+
+impl LaunchLog {
+    pub fn new(dir: PathBuf, max_logs: usize) -> Self {
+        LaunchLog { dir, max_logs }
+    }
+
+    fn record_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn log_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.log", id))
+    }
+
+    // Creates the record and output file for a new launch of `game_id`
+    pub fn start(&self, game_id: &str) -> Result<(LaunchRecord, File), io::Error> {
+        fs::create_dir_all(&self.dir)?;
+
+        // Launch IDs are millisecond timestamps, bumped on the rare collision
+        let mut id = unix_millis();
+        while self.record_path(id).exists() {
+            id += 1;
+        }
+
+        let record = LaunchRecord {
+            id,
+            game_id: game_id.to_owned(),
+            started: unix_time(),
+            ended: None,
+            code: None,
+            error: None,
+        };
+        self.save(&record)?;
+        let file = File::create(self.log_path(id))?;
+
+        if let Err(err) = self.rotate() {
+            println!("Failed to rotate launch logs: {}", err);
+        }
+        Ok((record, file))
+    }
+
+    pub fn save(&self, record: &LaunchRecord) -> Result<(), io::Error> {
+        let json =
+            serde_json::to_string(record).map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+        File::create(self.record_path(record.id))?.write_all(json.as_bytes())
+    }
+
+    // All recorded launches, newest first
+    pub fn list(&self) -> Result<Vec<LaunchRecord>, io::Error> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut records: Vec<LaunchRecord> = fs::read_dir(&self.dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+            .filter_map(|path| {
+                let mut json = String::new();
+                File::open(&path).ok()?.read_to_string(&mut json).ok()?;
+                serde_json::from_str(&json).ok()
+            })
+            .collect();
+        records.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(records)
+    }
+
+    pub fn read_log(&self, id: u64) -> Result<String, io::Error> {
+        let mut bytes = Vec::new();
+        File::open(self.log_path(id))?.read_to_end(&mut bytes)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn rotate(&self) -> Result<(), io::Error> {
+        for record in self.list()?.iter().skip(self.max_logs) {
+            fs::remove_file(self.record_path(record.id))?;
+            if self.log_path(record.id).exists() {
+                fs::remove_file(self.log_path(record.id))?;
+            }
+        }
+        Ok(())
+    }
+}
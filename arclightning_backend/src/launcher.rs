@@ -0,0 +1,293 @@
+use config::{Game, Hook};
+use hooks::{HookCommand, DEFAULT_HOOK_TIMEOUT};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use supervisor::SessionLimit;
+
+// exe_path of games launched through Steam, followed by the app ID
+pub const STEAM_RUN_PREFIX: &str = "steam://rungameid/";
+
+// Per-launch values substituted into a game's exe_args
+#[derive(Debug, Clone, Default)]
+pub struct LaunchContext {
+    pub static_dir: PathBuf,
+    pub players: Vec<String>,
+}
+
+// Everything the supervisor needs to run a game and its hooks
+#[derive(Debug)]
+pub struct Launch {
+    pub command: Command,
+    pub pre_launch: Vec<HookCommand>,
+    pub post_exit: Vec<HookCommand>,
+    pub session_limit: Option<SessionLimit>,
+    // Players who checked in for this launch
+    pub players: Vec<String>,
+    pub relaunch: Option<Relaunch>,
+    // Set when the command only hands the game off to another program, such
+    // as Steam, and exits straight away. The game then runs unsupervised,
+    // see GameState::Detached.
+    pub detached: bool,
+}
+
+// Everything needed to build the same launch again after a crash
+#[derive(Debug, Clone)]
+pub struct Relaunch {
+    game: Game,
+    context: LaunchContext,
+    defaults: LaunchDefaults,
+}
+
+impl Relaunch {
+    pub fn build(&self) -> Result<Launch, io::Error> {
+        build_launch(&self.game, &self.context, &self.defaults)
+    }
+}
+
+impl Launch {
+    pub fn new(command: Command) -> Self {
+        Launch {
+            command,
+            pre_launch: Vec::new(),
+            post_exit: Vec::new(),
+            session_limit: None,
+            players: Vec::new(),
+            relaunch: None,
+            detached: false,
+        }
+    }
+}
+
+// Settings from the top level of the config, used by games that don't
+// override them
+#[derive(Debug, Clone, Default)]
+pub struct LaunchDefaults {
+    pub pre_launch: Vec<Hook>,
+    pub post_exit: Vec<Hook>,
+    pub max_session_minutes: Option<u64>,
+    pub session_warning_minutes: u64,
+    pub limit_only_when_waiting: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+// Splits a template into literal text and {placeholder} names.
+// "{{" and "}}" are escapes for literal braces.
+fn parse_template(template: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        if rest.starts_with("{{") {
+            segments.push(Segment::Text("{"));
+            rest = &rest[2..];
+        } else if rest.starts_with("}}") {
+            segments.push(Segment::Text("}"));
+            rest = &rest[2..];
+        } else if rest.starts_with('{') {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in \"{}\"", template))?;
+            segments.push(Segment::Placeholder(&rest[1..end]));
+            rest = &rest[end + 1..];
+        } else if rest.starts_with('}') {
+            return Err(format!("unmatched '}}' in \"{}\"", template));
+        } else {
+            let end = rest.find(&['{', '}'][..]).unwrap_or(rest.len());
+            segments.push(Segment::Text(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    Ok(segments)
+}
+
+// Returns the player index for "player<N>_name" placeholders
+fn player_index(name: &str) -> Option<usize> {
+    if name.starts_with("player") && name.ends_with("_name") {
+        name["player".len()..name.len() - "_name".len()]
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+    } else {
+        None
+    }
+}
+
+fn is_known_placeholder(name: &str) -> bool {
+    match name {
+        "game_dir" | "static_dir" | "player_count" => true,
+        _ => player_index(name).is_some(),
+    }
+}
+
+// Checks a template for syntax errors and unknown placeholders
+pub fn check_template(template: &str) -> Result<(), String> {
+    for segment in parse_template(template)? {
+        if let Segment::Placeholder(name) = segment {
+            if !is_known_placeholder(name) {
+                return Err(format!("unknown placeholder {{{}}}", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+// The Steam app ID of a game launched with a steam://rungameid/ URL
+pub fn steam_app_id(exe_path: &Path) -> Option<&str> {
+    exe_path.to_str()?.strip_prefix(STEAM_RUN_PREFIX)
+}
+
+// The directory containing the game's executable
+pub fn game_dir(game: &Game) -> Result<PathBuf, String> {
+    if steam_app_id(&game.exe_path).is_some() {
+        return game
+            .working_dir
+            .clone()
+            .ok_or_else(|| "Steam games need a working_dir for {game_dir}".to_owned());
+    }
+    match game.exe_path.parent() {
+        Some(parent) if parent != Path::new("") => Ok(parent.to_path_buf()),
+        _ => Err(format!(
+            "exe_path {:?} has no parent directory; use an absolute path or set working_dir",
+            game.exe_path
+        )),
+    }
+}
+
+fn lookup(name: &str, game: &Game, context: &LaunchContext) -> Result<String, String> {
+    match name {
+        "game_dir" => game_dir(game).map(|dir| dir.to_string_lossy().into_owned()),
+        "static_dir" => Ok(context.static_dir.to_string_lossy().into_owned()),
+        // A launch with no named players still has someone at the stick
+        "player_count" => Ok(context.players.len().max(1).to_string()),
+        _ => match player_index(name) {
+            Some(n) => Ok(context.players.get(n - 1).cloned().unwrap_or_default()),
+            None => Err(format!("unknown placeholder {{{}}}", name)),
+        },
+    }
+}
+
+pub fn expand(template: &str, game: &Game, context: &LaunchContext) -> Result<String, String> {
+    let mut expanded = String::new();
+    for segment in parse_template(template)? {
+        match segment {
+            Segment::Text(text) => expanded.push_str(text),
+            Segment::Placeholder(name) => expanded.push_str(&lookup(name, game, context)?),
+        }
+    }
+    Ok(expanded)
+}
+
+fn expand_args(args: &[String], game: &Game, context: &LaunchContext) -> Result<Vec<String>, io::Error> {
+    args.iter()
+        .map(|arg| expand(arg, game, context))
+        .collect::<Result<Vec<String>, String>>()
+        .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))
+}
+
+// Creates a command for `program` in the game's working directory and
+// environment
+fn game_command(program: &Path, args: Vec<String>, game: &Game) -> Result<Command, io::Error> {
+    let working_dir = match game.working_dir {
+        Some(ref dir) => dir.clone(),
+        None => game_dir(game).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?,
+    };
+
+    let mut command = Command::new(program);
+    command.args(args).current_dir(working_dir);
+    if game.env_clear {
+        command.env_clear();
+    }
+    command.envs(&game.env);
+    Ok(command)
+}
+
+// Hands a URL to the desktop, which passes it on to Steam
+fn open_url(url: &str) -> Command {
+    let mut command;
+    if cfg!(windows) {
+        command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+    } else if cfg!(target_os = "macos") {
+        command = Command::new("open");
+    } else {
+        command = Command::new("xdg-open");
+    }
+    command.arg(url);
+    command
+}
+
+// Builds the command used to start a game, with exe_args expanded and the
+// game's working directory and environment applied
+pub fn build_command(game: &Game, context: &LaunchContext) -> Result<Command, io::Error> {
+    if steam_app_id(&game.exe_path).is_some() {
+        // Steam starts the game itself, so exe_args, the working directory
+        // and the environment don't reach it
+        return Ok(open_url(&game.exe_path.to_string_lossy()));
+    }
+    let args = expand_args(&game.exe_args, game, context)?;
+    game_command(&game.exe_path, args, game)
+}
+
+fn build_hooks(
+    hooks: &[Hook],
+    game: &Game,
+    context: &LaunchContext,
+) -> Result<Vec<HookCommand>, io::Error> {
+    hooks
+        .iter()
+        .map(|hook| {
+            let args = expand_args(&hook.args, game, context)?;
+            Ok(HookCommand {
+                command: game_command(&hook.command, args, game)?,
+                timeout: hook
+                    .timeout_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_HOOK_TIMEOUT),
+            })
+        })
+        .collect()
+}
+
+pub fn build_launch(
+    game: &Game,
+    context: &LaunchContext,
+    defaults: &LaunchDefaults,
+) -> Result<Launch, io::Error> {
+    let pre_launch = game.pre_launch.as_ref().unwrap_or(&defaults.pre_launch);
+    let post_exit = game.post_exit.as_ref().unwrap_or(&defaults.post_exit);
+    let session_limit = game
+        .max_session_minutes
+        .or(defaults.max_session_minutes)
+        .map(|minutes| {
+            let max = Duration::from_secs(minutes.saturating_mul(60));
+            let warning = Duration::from_secs(defaults.session_warning_minutes.saturating_mul(60));
+            SessionLimit {
+                max,
+                // A warning as long as the session would go off at launch,
+                // so short sessions are warned halfway through instead
+                warning: warning.min(max / 2),
+                only_when_waiting: defaults.limit_only_when_waiting,
+            }
+        });
+
+    Ok(Launch {
+        command: build_command(game, context)?,
+        pre_launch: build_hooks(pre_launch, game, context)?,
+        post_exit: build_hooks(post_exit, game, context)?,
+        session_limit,
+        players: context.players.clone(),
+        relaunch: Some(Relaunch {
+            game: game.clone(),
+            context: context.clone(),
+            defaults: defaults.clone(),
+        }),
+        detached: steam_app_id(&game.exe_path).is_some(),
+    })
+}
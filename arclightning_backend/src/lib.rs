@@ -1,14 +1,40 @@
+extern crate argon2;
 extern crate bcrypt;
 extern crate futures;
 extern crate hyper;
 extern crate hyper_staticfile;
+#[cfg(unix)]
+extern crate libc;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate rand;
+#[macro_use]
 extern crate serde_json;
 extern crate toml;
+extern crate toml_edit;
+extern crate xmltree;
 
+pub mod accounts;
+pub mod blocking;
+pub mod catalog;
+pub mod check;
 pub mod config;
+pub mod document;
+pub mod frontends;
+pub mod history;
+pub mod hooks;
+pub mod launch_log;
+pub mod launcher;
+pub mod migrate;
+pub mod options;
 pub mod password;
+pub mod process;
+pub mod reload;
 pub mod router;
+pub mod sessions;
+pub mod steam;
+pub mod supervisor;
 pub mod tests;
+pub mod throttle;
+pub mod validate;
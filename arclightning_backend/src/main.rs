@@ -1,44 +1,76 @@
+extern crate argon2;
 extern crate bcrypt;
 extern crate futures;
 extern crate hyper;
 extern crate hyper_staticfile;
+#[cfg(unix)]
+extern crate libc;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate rand;
+#[macro_use]
 extern crate serde_json;
 extern crate toml;
+extern crate toml_edit;
 
+mod blocking;
+mod catalog;
 mod config;
+mod document;
+mod history;
+mod hooks;
+mod launch_log;
+mod launcher;
+mod migrate;
+mod options;
 mod password;
+mod process;
+mod reload;
 mod router;
-mod tests;
+mod sessions;
+mod steam;
+mod supervisor;
+mod throttle;
+mod validate;
 
 use config::{Config, Game};
-use futures::Future;
+use futures::{future, Future};
+use hyper::server::conn::AddrStream;
+use hyper::service::make_service_fn;
 use hyper::Server;
-use password::check_password;
+use options::Options;
 
 use std::collections::HashMap;
 use std::io;
-use std::path::PathBuf;
+use std::time::Duration;
 
 fn main() -> Result<(), io::Error> {
-    // Read initial games toml config
-    let toml_filepath: PathBuf = ["server_config.toml"].iter().collect();
+    let options = Options::from_env()?;
+    if options.help {
+        println!("Usage: arclightning_backend [options]\n\n{}", options::USAGE);
+        return Ok(());
+    }
 
-    // Unpack config
-    let config: Config = Config::load(&toml_filepath)?;
+    // Unpack config, with any command line or environment overrides
+    let config: Config = options.load_config()?;
 
     // Host server
-    let addr = ([127, 0, 0, 1], config.listen_port).into();
+    let addr = config.listen_addr();
 
-    println!("Using assets directory: {:?}", config.static_dir);
+    println!("Using assets directory: {:?}", config.static_dir_path());
 
     // put the games data into the router struct
-    let router = router::Router::new(config);
+    let router = router::Router::new(config).with_options(options);
+    router.watch_config();
+    router.purge_sessions_every(Duration::from_secs(60));
 
+    // Each connection gets a router that knows the client's address, so
+    // failed logins can be throttled per client
     let server = Server::bind(&addr)
-        .serve(router)
+        .serve(make_service_fn(move |socket: &AddrStream| {
+            future::ok::<_, io::Error>(router.for_client(socket.remote_addr().ip()))
+        }))
         .map_err(|err| eprintln!("server error: {}", err));
 
     println!("Listening on http://{}", addr);
@@ -0,0 +1,360 @@
+// Upgrades config documents written for older versions of the server.
+//
+// Every config has a `config_version`; documents without one are version 1.
+// Each migration takes a document from one version to the next, so an old
+// config is upgraded step by step until it reaches CONFIG_VERSION. The
+// files in games_dir have no version of their own and are upgraded along
+// with the config that points at them.
+
+use config::{games_dir_files, write_atomically};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use toml_edit::{value, Decor, Document, Item, Key, Table};
+
+pub const CONFIG_VERSION: u32 = 2;
+
+struct Migration {
+    // The version documents have after this migration
+    to: u32,
+    description: &'static str,
+    // Applied to the top level of server_config.toml
+    config: fn(&mut Table),
+    // Applied to every game, inline or in games_dir
+    game: fn(&mut Table),
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    to: 2,
+    description: "move password into [auth] and rename genres to tags",
+    config: password_to_auth,
+    game: genres_to_tags,
+}];
+
+// Renames `from` to `to`, keeping its place among the other keys
+fn rename_key(table: &mut Table, from: &str, to: &str) {
+    if !table.contains_key(from) || table.contains_key(to) {
+        return;
+    }
+
+    let keys: Vec<String> = table.iter().map(|(key, _)| key.to_owned()).collect();
+    let mut moved = Vec::new();
+    for key in keys.iter().skip_while(|key| *key != from) {
+        if let Some(entry) = table.remove_entry(key) {
+            moved.push(entry);
+        }
+    }
+    for (key, item) in moved {
+        if key.get() == from {
+            let renamed = Key::new(to).with_decor(key.decor().clone());
+            table.insert_formatted(&renamed, item);
+        } else {
+            table.insert_formatted(&key, item);
+        }
+    }
+}
+
+fn password_to_auth(config: &mut Table) {
+    let password = match config.remove("password") {
+        Some(password) => password,
+        None => return,
+    };
+
+    // Keep whatever else is already set under [auth]. The old password was
+    // the one in use, so it wins over any password_hash there.
+    if let Some(auth) = config.get_mut("auth").and_then(Item::as_table_mut) {
+        auth.insert("password_hash", password);
+        return;
+    }
+    let mut auth = Table::new();
+    auth.insert("password_hash", password);
+    // Straight after the top level keys, before any other table
+    auth.set_position(0);
+    config.insert("auth", Item::Table(auth));
+}
+
+fn genres_to_tags(game: &mut Table) {
+    rename_key(game, "genres", "tags");
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+// The config_version of a document
+pub fn version(document: &Document) -> Result<u32, io::Error> {
+    let version = match document.as_table().get("config_version") {
+        None => return Ok(1),
+        Some(item) => item.as_integer(),
+    };
+    match version {
+        Some(version) if version >= 1 && version <= i64::from(CONFIG_VERSION) => Ok(version as u32),
+        Some(version) if version > i64::from(CONFIG_VERSION) => Err(invalid(format!(
+            "config_version {} is newer than this server supports ({})",
+            version, CONFIG_VERSION
+        ))),
+        _ => Err(invalid(
+            "config_version must be a positive integer".to_owned(),
+        )),
+    }
+}
+
+fn parse(path: &Path, source: &str) -> Result<Document, io::Error> {
+    source
+        .parse()
+        .map_err(|err| invalid(format!("{}: {}", path.display(), err)))
+}
+
+fn read(path: &Path) -> Result<String, io::Error> {
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+    Ok(source)
+}
+
+// A file rewritten by an upgrade
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileUpgrade {
+    pub path: PathBuf,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upgrade {
+    pub from: u32,
+    pub steps: Vec<&'static str>,
+    // The config itself first, then any changed files in games_dir
+    pub files: Vec<FileUpgrade>,
+}
+
+impl Upgrade {
+    // Copies every file about to be rewritten to `<file>.v<version>.bak`,
+    // then writes the upgraded files
+    pub fn apply(&self) -> Result<(), io::Error> {
+        for file in &self.files {
+            let mut backup = file.path.clone().into_os_string();
+            backup.push(format!(".v{}.bak", self.from));
+            fs::copy(&file.path, &backup)?;
+        }
+        for file in &self.files {
+            write_atomically(&file.path, &file.new)?;
+        }
+        Ok(())
+    }
+
+    pub fn diff(&self) -> String {
+        self.files
+            .iter()
+            .map(|file| diff(&file.path, &file.old, &file.new))
+            .collect()
+    }
+}
+
+// Upgrades a config document that is already in memory, along with the
+// games defined inline. Returns the migrations that were applied.
+pub fn migrate_document(document: &mut Document) -> Result<Vec<&'static str>, io::Error> {
+    let from = version(document)?;
+    let mut steps = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|migration| migration.to > from) {
+        (migration.config)(document.as_table_mut());
+        if let Some(games) = document
+            .as_table_mut()
+            .get_mut("games")
+            .and_then(Item::as_table_like_mut)
+        {
+            for (_, game) in games.iter_mut() {
+                if let Some(game) = game.as_table_mut() {
+                    (migration.game)(game);
+                }
+            }
+        }
+        steps.push(migration.description);
+    }
+
+    if from < CONFIG_VERSION {
+        let config = document.as_table_mut();
+        match config.get_mut("config_version") {
+            Some(item) => {
+                // Keep any comment next to the old version
+                let decor = item.as_value().map(|value| value.decor().clone());
+                *item = value(i64::from(CONFIG_VERSION));
+                if let (Some(decor), Some(value)) = (decor, item.as_value_mut()) {
+                    *value.decor_mut() = decor;
+                }
+            }
+            None => {
+                // Put the version first, below any comment at the top of
+                // the file
+                let keys: Vec<String> = config.iter().map(|(key, _)| key.to_owned()).collect();
+                let mut entries: Vec<(Key, Item)> = keys
+                    .iter()
+                    .filter_map(|key| config.remove_entry(key))
+                    .collect();
+
+                let mut version_key = Key::new("config_version");
+                if let Some(&mut (ref mut first, ref item)) = entries.first_mut() {
+                    if item.is_value() {
+                        let prefix = first.decor().prefix().unwrap_or("").to_owned();
+                        let suffix = first.decor().suffix().unwrap_or(" ").to_owned();
+                        version_key = version_key.with_decor(Decor::new(prefix, " "));
+                        *first.decor_mut() = Decor::new("", suffix);
+                    }
+                }
+                config.insert_formatted(&version_key, value(i64::from(CONFIG_VERSION)));
+                for (key, item) in entries {
+                    config.insert_formatted(&key, item);
+                }
+            }
+        }
+    }
+    Ok(steps)
+}
+
+// Upgrades a single game from a games_dir written for version `from`
+pub fn migrate_game(game: &mut Table, from: u32) {
+    for migration in MIGRATIONS.iter().filter(|migration| migration.to > from) {
+        (migration.game)(game);
+    }
+}
+
+// Works out how to upgrade the config at `path` without changing anything.
+// Returns None when it is already up to date.
+pub fn plan(path: &Path) -> Result<Option<Upgrade>, io::Error> {
+    let old = read(path)?;
+    let mut document = parse(path, &old)?;
+    let from = version(&document)?;
+    if from == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    let steps = migrate_document(&mut document)?;
+    let mut files = vec![FileUpgrade {
+        path: path.to_path_buf(),
+        old,
+        new: document.to_string(),
+    }];
+
+    let games_dir = document
+        .as_table()
+        .get("games_dir")
+        .and_then(Item::as_str)
+        .map(|dir| path.parent().unwrap_or_else(|| Path::new("")).join(dir));
+    if let Some(dir) = games_dir {
+        for game_path in games_dir_files(&dir)? {
+            let old = read(&game_path)?;
+            let mut game = parse(&game_path, &old)?;
+            migrate_game(game.as_table_mut(), from);
+            let new = game.to_string();
+            if new != old {
+                files.push(FileUpgrade {
+                    path: game_path,
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+
+    Ok(Some(Upgrade { from, steps, files }))
+}
+
+// Upgrades the config at `path` in place if it was written for an older
+// version, keeping backups of the old files
+pub fn upgrade(path: &Path) -> Result<Option<Upgrade>, io::Error> {
+    let upgrade = match plan(path)? {
+        Some(upgrade) => upgrade,
+        None => return Ok(None),
+    };
+
+    upgrade.apply().map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "Failed to upgrade {} to config_version {}: {}",
+                path.display(),
+                CONFIG_VERSION,
+                err
+            ),
+        )
+    })?;
+    println!(
+        "Upgraded {} from config_version {} to {} ({}), backups end in .v{}.bak",
+        path.display(),
+        upgrade.from,
+        CONFIG_VERSION,
+        upgrade.steps.join("; "),
+        upgrade.from
+    );
+    Ok(Some(upgrade))
+}
+
+// Line diff of `old` and `new` in unified format, with two lines of context
+pub fn diff(path: &Path, old: &str, new: &str) -> String {
+    const CONTEXT: usize = 2;
+
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence lengths of every pair of suffixes
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    // Each line tagged with ' ', '-' or '+', and the number of lines of
+    // `old` and `new` before it
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            lines.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            lines.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+
+    let mut output = String::new();
+    let changed: Vec<usize> = (0..lines.len()).filter(|&n| lines[n].0 != ' ').collect();
+    if changed.is_empty() {
+        return output;
+    }
+    output.push_str(&format!("--- {}\n+++ {}\n", path.display(), path.display()));
+
+    let mut n = 0;
+    while n < changed.len() {
+        let start = changed[n].saturating_sub(CONTEXT);
+        let mut end = changed[n];
+        while n < changed.len() && changed[n] <= end + 2 * CONTEXT + 1 {
+            end = changed[n];
+            n += 1;
+        }
+        let end = (end + CONTEXT + 1).min(lines.len());
+
+        let hunk = &lines[start..end];
+        let old_count = hunk.iter().filter(|line| line.0 != '+').count();
+        let new_count = hunk.iter().filter(|line| line.0 != '-').count();
+        // An empty side starts at the line before it, as in diff -u
+        let old_start = hunk[0].2 + if old_count > 0 { 1 } else { 0 };
+        let new_start = hunk[0].3 + if new_count > 0 { 1 } else { 0 };
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_count, new_start, new_count
+        ));
+        for &(tag, line, _, _) in hunk {
+            output.push_str(&format!("{}{}\n", tag, line));
+        }
+    }
+    output
+}
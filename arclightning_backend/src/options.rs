@@ -0,0 +1,199 @@
+// Command line and environment overrides shared by the server binaries.
+//
+// Each setting is taken from the first of these that provides it:
+//   1. the command line flag, e.g. `--port 8080`
+//   2. the ARCLIGHTNING_* environment variable, e.g. ARCLIGHTNING_PORT=8080
+//   3. the value in server_config.toml
+//   4. the built-in default
+//
+// Overrides only apply to the running process; they are never written back
+// to the config file.
+
+use config::Config;
+use std::env;
+use std::io::{self, ErrorKind};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Options:
+    --config <path>      Config file to use [env: ARCLIGHTNING_CONFIG]
+                         (default: server_config.toml)
+    --bind <address>     IPv4 or IPv6 address to listen on, 0.0.0.0 or :: for
+                         every interface [env: ARCLIGHTNING_BIND]
+    --port <port>        Port to listen on [env: ARCLIGHTNING_PORT]
+    --static-dir <path>  Directory the frontend is served from
+                         [env: ARCLIGHTNING_STATIC_DIR]
+    -h, --help           Print this message
+
+Command line flags take precedence over environment variables, which take
+precedence over the values in the config file.";
+
+// For commands that write the config file, where overrides don't apply
+pub const CONFIG_USAGE: &str = "Options:
+    --config <path>      Config file to use [env: ARCLIGHTNING_CONFIG]
+                         (default: server_config.toml)
+    -h, --help           Print this message";
+
+const OVERRIDE_FLAGS: &[&str] = &["--bind", "--port", "--static-dir"];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Options {
+    pub config_path: PathBuf,
+    pub bind: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub static_dir: Option<PathBuf>,
+    pub help: bool,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, message)
+}
+
+fn parse_bind(value: &str, source: &str) -> Result<IpAddr, io::Error> {
+    // Allow the bracketed form used in URLs, e.g. [::1]
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map_err(|err| invalid(format!("{}: invalid address {:?}: {}", source, value, err)))
+}
+
+fn parse_port(value: &str, source: &str) -> Result<u16, io::Error> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(invalid(format!(
+            "{}: port must be between 1 and 65535, got {:?}",
+            source, value
+        ))),
+        Ok(port) => Ok(port),
+    }
+}
+
+impl Options {
+    // Options for this process from std::env
+    pub fn from_env() -> Result<Options, io::Error> {
+        Options::parse(env::args().skip(1), |name| env::var(name).ok())
+    }
+
+    // Like from_env, for commands that write the config file: only the
+    // config path is taken, and override flags are rejected rather than
+    // silently ignored
+    pub fn config_only_from_env() -> Result<Options, io::Error> {
+        Options::parse_config_only(env::args().skip(1), |name| env::var(name).ok())
+    }
+
+    // `args` excludes the program name. `var` looks up environment
+    // variables, so tests don't need to touch the real environment.
+    pub fn parse<I, F>(args: I, var: F) -> Result<Options, io::Error>
+    where
+        I: IntoIterator<Item = String>,
+        F: Fn(&str) -> Option<String>,
+    {
+        Options::parse_flags(args, var, true)
+    }
+
+    pub fn parse_config_only<I, F>(args: I, var: F) -> Result<Options, io::Error>
+    where
+        I: IntoIterator<Item = String>,
+        F: Fn(&str) -> Option<String>,
+    {
+        Options::parse_flags(args, var, false)
+    }
+
+    fn parse_flags<I, F>(args: I, var: F, overrides: bool) -> Result<Options, io::Error>
+    where
+        I: IntoIterator<Item = String>,
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut options = Options::default();
+        let mut config_path = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                options.help = true;
+                continue;
+            }
+
+            // Both `--flag value` and `--flag=value` are accepted
+            let (flag, inline_value) = match arg.find('=') {
+                Some(equals) if arg.starts_with("--") => {
+                    (arg[..equals].to_owned(), Some(arg[equals + 1..].to_owned()))
+                }
+                _ => (arg.clone(), None),
+            };
+            if flag != "--config" && !OVERRIDE_FLAGS.contains(&flag.as_str()) {
+                return Err(invalid(format!("Unknown argument {:?}", arg)));
+            }
+            if !overrides && flag != "--config" {
+                return Err(invalid(format!(
+                    "{} can't be used here: overrides are never written to the config file",
+                    flag
+                )));
+            }
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(invalid(format!("{} requires a value", flag))),
+            };
+
+            match flag.as_str() {
+                "--config" => config_path = Some(PathBuf::from(value)),
+                "--bind" => options.bind = Some(parse_bind(&value, "--bind")?),
+                "--port" => options.port = Some(parse_port(&value, "--port")?),
+                _ => options.static_dir = Some(PathBuf::from(value)),
+            }
+        }
+
+        // The environment only fills in what the command line left unset,
+        // so a bad variable doesn't get in the way of a flag replacing it
+        options.config_path = config_path
+            .or_else(|| var("ARCLIGHTNING_CONFIG").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("server_config.toml"));
+        if !overrides {
+            return Ok(options);
+        }
+        if options.bind.is_none() {
+            if let Some(bind) = var("ARCLIGHTNING_BIND") {
+                options.bind = Some(parse_bind(&bind, "ARCLIGHTNING_BIND")?);
+            }
+        }
+        if options.port.is_none() {
+            if let Some(port) = var("ARCLIGHTNING_PORT") {
+                options.port = Some(parse_port(&port, "ARCLIGHTNING_PORT")?);
+            }
+        }
+        if options.static_dir.is_none() {
+            options.static_dir = var("ARCLIGHTNING_STATIC_DIR").map(PathBuf::from);
+        }
+
+        Ok(options)
+    }
+
+    // Replaces the config values that were overridden
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.listen_address = bind;
+        }
+        if let Some(port) = self.port {
+            config.listen_port = port;
+        }
+        // Unlike paths in the config file, overrides are relative to the
+        // working directory
+        if let Some(ref static_dir) = self.static_dir {
+            config.static_dir = env::current_dir()
+                .map(|dir| dir.join(static_dir))
+                .unwrap_or_else(|_| static_dir.clone());
+        }
+    }
+
+    // Loads the config file with the overrides applied
+    pub fn load_config(&self) -> Result<Config, io::Error> {
+        let mut config = Config::load(&self.config_path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Failed to load {}: {}", self.config_path.display(), err),
+            )
+        })?;
+        self.apply(&mut config);
+        Ok(config)
+    }
+}
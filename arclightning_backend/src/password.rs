@@ -1,6 +1,239 @@
-pub extern crate blake2_rfc;
-extern crate hex;
-pub use password::blake2_rfc::blake2b::blake2b;
-pub fn check_password(password: &str, hash: &[u8]) -> bool {
-    hex::encode(blake2b(64, &[], password.as_bytes())).as_bytes() == hash
+// Hashing and checking passwords. Everything that stores or checks a
+// password goes through here.
+//
+// Hashes are stored as PHC-style strings, which carry their own scheme and
+// work factor:
+//
+//   bcrypt    $2b$12$<22 characters of salt><31 characters of hash>
+//   argon2id  $argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>
+//
+// New hashes are made with the scheme and work factor in [auth]. A stored
+// hash that is weaker than that still verifies, and is replaced with a new
+// one the next time its password is checked.
+
+use argon2::{self, Variant, Version};
+use bcrypt;
+use rand::{self, Rng};
+use std::io::{self, ErrorKind};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scheme {
+    Bcrypt,
+    Argon2id,
+}
+
+impl Default for Scheme {
+    fn default() -> Self {
+        Scheme::Bcrypt
+    }
+}
+
+// How new hashes are made, from [auth] in the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HashPolicy {
+    pub scheme: Scheme,
+    // log2 of the bcrypt rounds, 4 to 31
+    pub bcrypt_cost: u32,
+    // Memory argon2id uses per hash, in KiB, and passes over it
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+}
+
+impl Default for HashPolicy {
+    fn default() -> Self {
+        HashPolicy {
+            scheme: Scheme::default(),
+            bcrypt_cost: 12,
+            argon2_memory_kib: 19 * 1024,
+            argon2_iterations: 2,
+        }
+    }
+}
+
+// The scheme and work factor of a stored hash
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stored {
+    Bcrypt { cost: u32 },
+    Argon2id { memory_kib: u32, iterations: u32 },
+}
+
+fn is_base64(text: &str, extra: &[char]) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || extra.contains(&c))
+}
+
+fn parse_bcrypt(parts: &[&str]) -> Option<Stored> {
+    if parts.len() != 4 || !parts[0].is_empty() {
+        return None;
+    }
+    match parts[1] {
+        "2a" | "2b" | "2x" | "2y" => {}
+        _ => return None,
+    }
+    let cost = if parts[2].len() == 2 {
+        parts[2]
+            .parse::<u32>()
+            .ok()
+            .filter(|cost| *cost >= 4 && *cost <= 31)?
+    } else {
+        return None;
+    };
+    if parts[3].len() != 53 || !is_base64(parts[3], &['.', '/']) {
+        return None;
+    }
+    Some(Stored::Bcrypt { cost })
+}
+
+// `$argon2id$v=19$m=...,t=...,p=...$salt$hash`, where the version may be
+// left out
+fn parse_argon2id(parts: &[&str]) -> Option<Stored> {
+    if parts.len() < 2 || !parts[0].is_empty() || parts[1] != "argon2id" {
+        return None;
+    }
+    let rest = match parts.get(2) {
+        Some(version) if version.starts_with("v=") => {
+            if *version != "v=19" && *version != "v=16" {
+                return None;
+            }
+            &parts[3..]
+        }
+        _ => &parts[2..],
+    };
+    if rest.len() != 3 || !is_base64(rest[1], &['+', '/']) || !is_base64(rest[2], &['+', '/']) {
+        return None;
+    }
+
+    let (mut memory_kib, mut iterations, mut lanes) = (None, None, None);
+    for param in rest[0].split(',') {
+        let mut pair = param.splitn(2, '=');
+        let value = pair.next().and_then(|key| {
+            let value = pair.next()?.parse::<u32>().ok()?;
+            Some((key, value))
+        });
+        match value {
+            Some(("m", value)) => memory_kib = Some(value),
+            Some(("t", value)) => iterations = Some(value),
+            Some(("p", value)) => lanes = Some(value),
+            _ => return None,
+        }
+    }
+    match (memory_kib, iterations, lanes) {
+        (Some(memory_kib), Some(iterations), Some(lanes)) if lanes > 0 && iterations > 0 => {
+            Some(Stored::Argon2id {
+                memory_kib,
+                iterations,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse(hash: &str) -> Option<Stored> {
+    let parts: Vec<&str> = hash.split('$').collect();
+    parse_bcrypt(&parts).or_else(|| parse_argon2id(&parts))
+}
+
+fn unrecognized() -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        "The stored password hash is not a recognized bcrypt or argon2id hash".to_owned(),
+    )
+}
+
+// Whether `hash` is a bcrypt or argon2id hash this module can check
+pub fn is_valid_hash(hash: &str) -> bool {
+    parse(hash).is_some()
+}
+
+// Hashes `password` with a new random salt
+pub fn hash(password: &str, policy: &HashPolicy) -> Result<String, io::Error> {
+    match policy.scheme {
+        Scheme::Bcrypt => bcrypt::hash(password, policy.bcrypt_cost).map_err(|err| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Failed to hash password with bcrypt: {}", err),
+            )
+        }),
+        Scheme::Argon2id => {
+            let mut salt = [0u8; 16];
+            rand::thread_rng().fill(&mut salt[..]);
+            let config = argon2::Config {
+                variant: Variant::Argon2id,
+                version: Version::Version13,
+                mem_cost: policy.argon2_memory_kib,
+                time_cost: policy.argon2_iterations,
+                ..argon2::Config::default()
+            };
+            argon2::hash_encoded(password.as_bytes(), &salt, &config).map_err(|err| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Failed to hash password with argon2id: {}", err),
+                )
+            })
+        }
+    }
+}
+
+// Whether `password` matches the stored `hash`
+pub fn verify(password: &str, hash: &str) -> Result<bool, io::Error> {
+    let verified = match parse(hash).ok_or_else(unrecognized)? {
+        Stored::Bcrypt { .. } => bcrypt::verify(password, hash).map_err(|err| err.to_string()),
+        Stored::Argon2id { .. } => {
+            argon2::verify_encoded(hash, password.as_bytes()).map_err(|err| err.to_string())
+        }
+    };
+    verified.map_err(|err| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Failed to check password hash: {}", err),
+        )
+    })
+}
+
+// Whether `hash` should be replaced to meet `policy`: it uses another
+// scheme or a lower work factor. Stronger hashes are left alone.
+pub fn needs_rehash(hash: &str, policy: &HashPolicy) -> bool {
+    match (parse(hash), policy.scheme) {
+        (Some(Stored::Bcrypt { cost }), Scheme::Bcrypt) => cost < policy.bcrypt_cost,
+        (
+            Some(Stored::Argon2id {
+                memory_kib,
+                iterations,
+            }),
+            Scheme::Argon2id,
+        ) => memory_kib < policy.argon2_memory_kib || iterations < policy.argon2_iterations,
+        _ => true,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verified {
+    Wrong,
+    Correct,
+    // Correct, and this hash should be stored in place of the old one
+    Rehashed(String),
+}
+
+// Checks `password` against the stored `hash`, rehashing it if it is weaker
+// than `policy`. A failed rehash is logged and the old hash kept.
+pub fn check_password(
+    password: &str,
+    hash: &str,
+    policy: &HashPolicy,
+) -> Result<Verified, io::Error> {
+    if !verify(password, hash)? {
+        return Ok(Verified::Wrong);
+    }
+    if needs_rehash(hash, policy) {
+        // The password was right, so a policy that can't hash doesn't get
+        // to fail the login
+        match self::hash(password, policy) {
+            Ok(new_hash) => return Ok(Verified::Rehashed(new_hash)),
+            Err(err) => println!("Failed to rehash password: {}", err),
+        }
+    }
+    Ok(Verified::Correct)
 }
@@ -0,0 +1,124 @@
+// Launcher-style games start the real game and exit, so a game is tracked
+// as a whole process group rather than as the single child we spawned.
+
+use std::io;
+use std::process::{Child, Command, ExitStatus};
+
+#[cfg(unix)]
+mod imp {
+    use libc;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command, ExitStatus};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+    // How long kill_group waits for the group to be gone after SIGKILL
+    const KILL_WAIT: Duration = Duration::from_secs(5);
+
+    pub fn new_process_group(command: &mut Command) {
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setpgid(0, 0) == 0 {
+                    Ok(())
+                } else {
+                    Err(io::Error::last_os_error())
+                }
+            });
+        }
+    }
+
+    // The child's process group has the same ID as the child
+    fn signal_group(child: &Child, signal: libc::c_int) -> Result<(), io::Error> {
+        if unsafe { libc::kill(-(child.id() as libc::pid_t), signal) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ESRCH) {
+            // Everything in the group already exited
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
+    pub fn group_alive(child: &Child) -> bool {
+        unsafe { libc::kill(-(child.id() as libc::pid_t), 0) == 0 }
+    }
+
+    pub fn request_exit(child: &mut Child) -> Result<(), io::Error> {
+        signal_group(child, libc::SIGTERM)
+    }
+
+    pub fn force_exit(child: &mut Child) -> Result<(), io::Error> {
+        signal_group(child, libc::SIGKILL)
+    }
+
+    pub fn kill_group(child: &mut Child) -> Result<ExitStatus, io::Error> {
+        signal_group(child, libc::SIGKILL)?;
+        let status = child.wait()?;
+
+        // SIGKILL can't be ignored, but the kernel may take a moment
+        let deadline = Instant::now() + KILL_WAIT;
+        while group_alive(child) && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        if group_alive(child) {
+            println!("Process group {} is still alive after SIGKILL", child.id());
+        }
+        Ok(status)
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+    use std::process::{Child, Command, ExitStatus};
+
+    pub fn new_process_group(_command: &mut Command) {}
+
+    pub fn group_alive(_child: &Child) -> bool {
+        false
+    }
+
+    pub fn request_exit(child: &mut Child) -> Result<(), io::Error> {
+        child.kill()
+    }
+
+    pub fn force_exit(child: &mut Child) -> Result<(), io::Error> {
+        child.kill()
+    }
+
+    pub fn kill_group(child: &mut Child) -> Result<ExitStatus, io::Error> {
+        child.kill()?;
+        child.wait()
+    }
+}
+
+// Starts the command in a process group of its own
+pub fn new_process_group(command: &mut Command) {
+    imp::new_process_group(command)
+}
+
+// Whether any process in the child's group is still running. Only
+// meaningful for children spawned with new_process_group.
+pub fn group_alive(child: &Child) -> bool {
+    imp::group_alive(child)
+}
+
+// Asks every process in the child's group to exit, without waiting
+pub fn request_exit(child: &mut Child) -> Result<(), io::Error> {
+    imp::request_exit(child)
+}
+
+// Kills every process in the child's group, without waiting
+pub fn force_exit(child: &mut Child) -> Result<(), io::Error> {
+    imp::force_exit(child)
+}
+
+// Kills every process in the child's group at once and waits until they
+// are all gone
+pub fn kill_group(child: &mut Child) -> Result<ExitStatus, io::Error> {
+    imp::kill_group(child)
+}
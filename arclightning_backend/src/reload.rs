@@ -0,0 +1,98 @@
+use config::{games_dir_files, Config};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use toml;
+
+// How often the config file and games_dir are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[cfg(unix)]
+mod sighup {
+    use libc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn handle(_signal: libc::c_int) {
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn listen() {
+        let handler: extern "C" fn(libc::c_int) = handle;
+        unsafe {
+            libc::signal(libc::SIGHUP, handler as libc::sighandler_t);
+        }
+    }
+
+    pub fn take() -> bool {
+        RECEIVED.swap(false, Ordering::SeqCst)
+    }
+}
+
+#[cfg(not(unix))]
+mod sighup {
+    pub fn listen() {}
+
+    pub fn take() -> bool {
+        false
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// The games_dir of the config at `path`, if it has one and can be read
+fn read_games_dir(path: &Path) -> Option<PathBuf> {
+    let source = fs::read_to_string(path).ok()?;
+    let config: Config = toml::from_str(&source).ok()?;
+    config.games_dir_path(path)
+}
+
+// Modification times of the config and of everything in games_dir. The
+// directory itself changes when a game file is added or removed.
+fn snapshot(path: &Path, games_dir: &Option<PathBuf>) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files = vec![path.to_path_buf()];
+    if let Some(ref dir) = *games_dir {
+        files.push(dir.clone());
+        files.extend(games_dir_files(dir).unwrap_or_default());
+    }
+    files
+        .into_iter()
+        .map(|file| {
+            let time = modified(&file);
+            (file, time)
+        })
+        .collect()
+}
+
+// Calls `reload` whenever the config file at `path` or a game file in its
+// games_dir is modified or, on unix, the process receives SIGHUP
+pub fn watch<F>(path: PathBuf, reload: F)
+where
+    F: Fn() + Send + 'static,
+{
+    sighup::listen();
+
+    thread::spawn(move || {
+        let mut games_dir = read_games_dir(&path);
+        let mut last = snapshot(&path, &games_dir);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let current = snapshot(&path, &games_dir);
+            // The config may be missing for a moment while it is replaced
+            let changed = current[0].1.is_some() && current != last;
+            if sighup::take() || changed {
+                // The new config may point somewhere else
+                games_dir = read_games_dir(&path);
+                last = snapshot(&path, &games_dir);
+                reload();
+            }
+        }
+    });
+}
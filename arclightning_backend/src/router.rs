@@ -1,20 +1,29 @@
-// TODO: expire cookie after 12 hours
-// TODO: validate that cookie is sent to frontend correctly
-
-use bcrypt::verify;
-use config::{Config, Game};
+use blocking;
+use catalog::Query;
+use config::{self, Config, Game};
 use futures::{future, Stream};
-use hyper::header::{COOKIE, LOCATION, SET_COOKIE};
+use hyper::header::{LOCATION, RETRY_AFTER, SET_COOKIE, USER_AGENT};
 use hyper::rt::Future;
 use hyper::{Body, Error, Method, Request, Response, StatusCode};
-use rand::Rng;
+use history::{self, PlayHistory};
+use launch_log::{unix_time, LaunchLog};
+use launcher::{self, LaunchContext, LaunchDefaults};
+use options::Options;
+use password::{self, HashPolicy, Verified};
+use reload;
+use sessions::{self, SessionInfo, SessionPolicy, Sessions, SESSION_COOKIE};
 use std::collections::HashMap;
 use std::fs::read_dir;
 use std::io::{self, ErrorKind};
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::thread;
+use std::time::Duration;
+use steam::{self, SteamGame};
+use supervisor::{CrashPolicy, Supervisor};
+use throttle::{Throttle, ThrottlePolicy};
+use validate;
 
 type ResponseFuture = Box<Future<Item = Response<Body>, Error = io::Error> + Send>;
 
@@ -32,42 +41,217 @@ pub fn list_files(path: PathBuf) -> Result<Vec<PathBuf>, io::Error> {
     Ok(result)
 }
 
+// Decodes a query string value: `+` is a space and `%XX` a byte
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Returns the value of `key` from the request's query string
+fn query_param(request: &Request<Body>, key: &str) -> Option<String> {
+    request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                (parts.next().unwrap_or(""), parts.next().unwrap_or(""))
+            })
+            .find(|&(name, _)| name == key)
+            .map(|(_, value)| percent_decode(value))
+    })
+}
+
+// The time in the `key` query parameter, read with `parse`. A value that
+// isn't a time is an error rather than being ignored.
+fn time_param(
+    request: &Request<Body>,
+    key: &str,
+    parse: fn(&str) -> Option<u64>,
+) -> Result<Option<u64>, io::Error> {
+    match query_param(request, key) {
+        Some(value) => parse(&value).map(Some).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} must be a unix timestamp or YYYY-MM-DD date, got {:?}",
+                    key, value
+                ),
+            )
+        }),
+        None => Ok(None),
+    }
+}
+
+// The status for a failed edit through the API
+fn error_status(err: &io::Error) -> StatusCode {
+    match err.kind() {
+        ErrorKind::InvalidInput | ErrorKind::InvalidData => StatusCode::UNPROCESSABLE_ENTITY,
+        ErrorKind::AlreadyExists => StatusCode::CONFLICT,
+        ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+// The session token from the request's session cookie or, for clients
+// without cookies, its `Authorization: Bearer` header
+fn session_token(request: &Request<Body>) -> Result<String, io::Error> {
+    sessions::request_token(request.headers(), SESSION_COOKIE)
+        .ok_or_else(|| io::Error::new(ErrorKind::Other, "No session cookie or bearer token"))
+}
+
+fn print_problems(problems: &HashMap<String, Vec<String>>) {
+    for (id, problems) in problems {
+        for problem in problems {
+            println!("Game {} is unavailable: {}", id, problem);
+        }
+    }
+}
+
+// Everything the router takes from the config file. It is replaced as a
+// whole when the config is reloaded, so a request always sees one
+// consistent version of it.
 #[derive(Debug, Clone)]
-pub struct Router {
-    games: Arc<Mutex<HashMap<String, Game>>>,
+struct Settings {
+    games: HashMap<String, Game>,
     static_dir: PathBuf,
-    access_key: Arc<Mutex<Option<AccessKey>>>,
     password: String,
+    session_policy: SessionPolicy,
+    secure_cookie: bool,
+    throttle_policy: ThrottlePolicy,
+    hash_policy: HashPolicy,
+    launch_defaults: LaunchDefaults,
+    hide_unavailable_games: bool,
+    // Validation problems per game ID, from loading or the last revalidation
+    problems: HashMap<String, Vec<String>>,
+}
+
+impl Settings {
+    fn new(config: Config) -> Self {
+        let static_dir = config.static_dir_path();
+        let problems = validate::check_games(&config.games, &static_dir);
+        print_problems(&problems);
+
+        Settings {
+            games: config.games,
+            static_dir,
+            password: config.auth.password_hash.clone().unwrap_or_else(|| "".to_string()),
+            session_policy: config.auth.session_policy(),
+            secure_cookie: config.auth.secure_cookie,
+            throttle_policy: config.auth.throttle_policy(),
+            hash_policy: config.auth.hash_policy(),
+            launch_defaults: LaunchDefaults {
+                pre_launch: config.pre_launch,
+                post_exit: config.post_exit,
+                max_session_minutes: config.max_session_minutes,
+                session_warning_minutes: config.session_warning_minutes,
+                limit_only_when_waiting: config.limit_only_when_waiting,
+            },
+            hide_unavailable_games: config.hide_unavailable_games,
+            problems,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Router {
+    settings: Arc<Mutex<Arc<Settings>>>,
+    // Where the config is reloaded from and the overrides applied to it,
+    // if reloading is enabled
+    options: Option<Options>,
+    // Held while the config file is edited through the API, so two edits
+    // can't overwrite each other
+    edit_lock: Arc<Mutex<()>>,
+    sessions: Sessions,
+    // Failed logins, shared by every clone
+    throttle: Throttle,
+    // The address of the connection being served, see for_client
+    client: Option<IpAddr>,
+    supervisor: Supervisor,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 struct StartGameRequest {
     id: String,
+    #[serde(default)]
+    players: Vec<String>,
+}
+#[derive(Debug, Deserialize, Clone)]
+struct ClearHealthRequest {
+    id: String,
+}
+#[derive(Debug, Deserialize, Clone)]
+struct WaitlistRequest {
+    name: String,
 }
 #[derive(Debug, Deserialize, Clone)]
 struct PasswordRequest {
     password: String,
 }
+#[derive(Debug, Deserialize, Clone)]
+struct SteamImportRequest {
+    steam_dir: PathBuf,
+    // Game IDs to merge into the config
+    #[serde(default)]
+    ids: Vec<String>,
+}
 
-#[derive(Debug, Clone)]
-struct AccessKey {
-    access_key: String,
-    access_time: u64,
+// A Steam game as proposed by /api/v1/import/steam
+#[derive(Debug, Serialize)]
+struct SteamListing<'a> {
+    #[serde(flatten)]
+    steam_game: &'a SteamGame,
+    // Why the game can't be merged, if it can't
+    conflict: Option<String>,
 }
 
-impl AccessKey {
-    pub fn new(access_key: String, access_time: u64) -> Self {
-        AccessKey {
-            access_key: access_key,
-            access_time,
-        }
-    }
-    pub fn dummy() -> Self {
-        AccessKey {
-            access_key: "failure_key".to_owned(),
-            access_time: 0u64,
-        }
-    }
+// A game as shown by /api/v1/list_games
+#[derive(Debug, Serialize)]
+struct GameListing<'a> {
+    id: &'a String,
+    #[serde(flatten)]
+    game: &'a Game,
+    available: bool,
+    problems: Vec<String>,
+}
+
+// How a password attempt at /api/v1/check_password went
+#[derive(Debug)]
+enum Login {
+    Correct,
+    Wrong,
+    // Refused without checking the password, for this many seconds
+    Throttled(u64),
+}
+
+// A session as shown by /api/v1/sessions
+#[derive(Debug, Serialize)]
+struct SessionListing {
+    #[serde(flatten)]
+    session: SessionInfo,
+    // Whether this is the session making the request
+    current: bool,
 }
 
 impl hyper::service::Service for Router {
@@ -89,24 +273,204 @@ impl hyper::service::NewService for Router {
     type InitError = Error;
     fn new_service(&self) -> Self::Future {
         Box::new(future::ok(Self {
-            games: self.games.clone(),
-            static_dir: self.static_dir.clone(),
-            access_key: self.access_key.clone(),
-            password: self.password.clone(),
+            settings: self.settings.clone(),
+            options: self.options.clone(),
+            edit_lock: self.edit_lock.clone(),
+            sessions: self.sessions.clone(),
+            throttle: self.throttle.clone(),
+            client: self.client,
+            supervisor: self.supervisor.clone(),
         }))
     }
 }
 
 impl Router {
     pub fn new(config: Config) -> Self {
+        let supervisor = Supervisor::with_log(Some(LaunchLog::new(
+            config.log_dir.clone(),
+            config.max_launch_logs,
+        )))
+            .with_history(PlayHistory::new(config.history_path.clone()))
+            .with_health_file(config.history_path.with_file_name("game_health.json"))
+            .with_crash_policy(CrashPolicy {
+                grace: Duration::from_secs(config.crash_grace_secs),
+                retry: config.retry_on_crash,
+                max_consecutive: config.max_consecutive_crashes,
+            })
+            .with_kill_grace(Duration::from_secs(config.kill_grace_secs));
+        let throttle = Throttle::new().with_audit_log(config.auth.audit_log_path.clone());
+
         Router {
-            games: Arc::new(Mutex::new(config.games)),
-            static_dir: config.static_dir,
-            access_key: Arc::new(Mutex::new(None)),
-            password: config.password.unwrap_or_else(|| "".to_string()),
+            settings: Arc::new(Mutex::new(Arc::new(Settings::new(config)))),
+            options: None,
+            edit_lock: Arc::new(Mutex::new(())),
+            sessions: Sessions::new(),
+            throttle,
+            client: None,
+            supervisor,
         }
     }
 
+    // A router for a connection from `client`. Failed logins are counted
+    // per client address.
+    pub fn for_client(&self, client: IpAddr) -> Self {
+        let mut router = self.clone();
+        router.client = Some(client);
+        router
+    }
+
+    // Enables reloading the config from `options.config_path`. The
+    // overrides in `options` are applied again on every reload.
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    fn settings(&self) -> Result<Arc<Settings>, io::Error> {
+        self.settings.lock().map(|settings| settings.clone()).map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on settings: {}", err),
+            )
+        })
+    }
+
+    fn replace_settings(&self, settings: Settings) -> Result<(), io::Error> {
+        self.update_settings(|current| *current = settings)
+    }
+
+    // Applies `update` to a copy of the settings and swaps it in, so requests
+    // already holding the old settings are unaffected
+    fn update_settings<F, T>(&self, update: F) -> Result<T, io::Error>
+    where
+        F: FnOnce(&mut Settings) -> T,
+    {
+        let mut guard = self.settings.lock().map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on settings: {}", err),
+            )
+        })?;
+        let mut settings = (**guard).clone();
+        let result = update(&mut settings);
+        *guard = Arc::new(settings);
+        Ok(result)
+    }
+
+    // Re-reads the config file and swaps in its games, password and static
+    // dir. The running config is kept if the new one fails to load.
+    // Server and supervisor settings such as listen_port and log_dir only
+    // take effect on restart.
+    pub fn reload_config(&self) -> Result<(), io::Error> {
+        let options = self.options.as_ref().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::Other,
+                "Reloading is disabled: no config path was given".to_owned(),
+            )
+        })?;
+
+        let config = options.load_config()?;
+        self.replace_settings(Settings::new(config))?;
+        println!("Reloaded config from {:?}", options.config_path);
+        Ok(())
+    }
+
+    // Applies `edit` to the config as it is on disk, without overrides, and
+    // swaps in the result. `edit` is responsible for saving its changes.
+    fn edit_config<F, T>(&self, edit: F) -> Result<T, io::Error>
+    where
+        F: FnOnce(&mut Config, &PathBuf) -> Result<T, io::Error>,
+    {
+        let options = self.options.as_ref().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::Other,
+                "Editing is disabled: no config path was given".to_owned(),
+            )
+        })?;
+        let _guard = self.edit_lock.lock().map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on config edits: {}", err),
+            )
+        })?;
+
+        let mut config = Config::load(&options.config_path).map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to load {:?}: {}", options.config_path, err),
+            )
+        })?;
+        let result = edit(&mut config, &options.config_path)?;
+        options.apply(&mut config);
+        self.replace_settings(Settings::new(config))?;
+        Ok(result)
+    }
+
+    // Reloads the config whenever the file changes or, on unix, the server
+    // receives SIGHUP
+    pub fn watch_config(&self) {
+        let router = self.clone();
+        if let Some(ref options) = self.options {
+            reload::watch(options.config_path.clone(), move || {
+                if let Err(err) = router.reload_config() {
+                    println!("Keeping the running config, reload failed: {}", err);
+                }
+            });
+        }
+    }
+
+    // Removes expired sessions every `interval`, so sessions that are
+    // never used again don't pile up
+    pub fn purge_sessions_every(&self, interval: Duration) {
+        let router = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let purged = router
+                .settings()
+                .and_then(|settings| router.sessions.purge(&settings.session_policy, unix_time()));
+            match purged {
+                Ok(0) => {}
+                Ok(purged) => println!("Purged {} expired sessions", purged),
+                Err(err) => println!("Failed to purge sessions: {}", err),
+            }
+        });
+    }
+
+    // Everything currently stopping a game from being played. With
+    // `recheck`, the game's files are checked again instead of relying on
+    // the last validation, since they may have changed since.
+    fn game_problems(
+        &self,
+        settings: &Settings,
+        id: &str,
+        recheck: bool,
+    ) -> Result<Vec<String>, io::Error> {
+        let mut problems = match settings.games.get(id) {
+            Some(game) if recheck => validate::check_game(game, &settings.static_dir),
+            _ => settings.problems.get(id).cloned().unwrap_or_default(),
+        };
+
+        if !self.supervisor.is_healthy(id)? {
+            problems.push("crashed repeatedly; clear its health to make it available".to_owned());
+        }
+        Ok(problems)
+    }
+
+    fn json_response(&self, status: StatusCode, body: String) -> ResponseFuture {
+        Box::new(future::result(
+            Response::builder()
+                .status(status)
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .map_err(|err| {
+                    io::Error::new(
+                        ErrorKind::Other,
+                        format!("An error occured when building a response: {}", err),
+                    )
+                }),
+        ))
+    }
+
     fn invalid_endpoint(&self, root: &PathBuf, mut request: Request<Body>) -> ResponseFuture {
         // In case of an invalid endpoint, serve the static 404.html page
         *request.uri_mut() = hyper::Uri::from_static("/404.html");
@@ -168,18 +532,36 @@ impl Router {
         ))
     }
 
-    fn list_games(&self) -> ResponseFuture {
-        let (body, status) = match self
-            .games
-            .lock()
-            .map_err(|err| {
-                io::Error::new(
-                    ErrorKind::Other,
-                    format!("Failed to acquire mutex on games list: {}", err),
+    // Lists the games matching the query string, in the order it asks for
+    fn list_games(&self, request: &Request<Body>) -> ResponseFuture {
+        let query = match Query::parse(|key| query_param(request, key)) {
+            Ok(query) => query,
+            Err(err) => {
+                return self.json_response(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    json!({ "success": false, "error": err }).to_string(),
                 )
-            })
-            .and_then(|games| {
-                serde_json::to_string(&*games).map_err(|err| io::Error::new(ErrorKind::Other, err))
+            }
+        };
+
+        let (body, status) = match self
+            .settings()
+            .and_then(|settings| {
+                let mut listings: Vec<GameListing> = Vec::new();
+                for (id, game) in query.apply(&settings.games) {
+                    let problems = self.game_problems(&settings, id, false)?;
+                    let available = problems.is_empty();
+                    if available || !settings.hide_unavailable_games {
+                        listings.push(GameListing {
+                            id,
+                            game,
+                            available,
+                            problems,
+                        });
+                    }
+                }
+                serde_json::to_string(&listings)
+                    .map_err(|err| io::Error::new(ErrorKind::Other, err))
             })
             .map(Body::from)
         {
@@ -204,7 +586,8 @@ impl Router {
     }
 
     fn start_game(&self, request: Request<Body>) -> ResponseFuture {
-        let games = self.games.clone();
+        let supervisor = self.supervisor.clone();
+        let router = self.clone();
 
         let response = request
             .into_body()
@@ -218,60 +601,434 @@ impl Router {
             .and_then(|body| {
                 serde_json::from_slice(&body).map_err(|err| io::Error::new(ErrorKind::Other, err))
             })
+            // Pre-launch hooks can take a while, so launching stays off the
+            // reactor
             .and_then(move |request_body: StartGameRequest| {
-                let games = games.lock().map_err(|_e| {
+                blocking::run(move || {
+                    router.settings().and_then(|settings| {
+                        let game = settings.games.get(&request_body.id).ok_or_else(|| {
+                            io::Error::new(
+                                ErrorKind::Other,
+                                "Failed to find game in list of available games".to_owned(),
+                            )
+                        })?;
+
+                        let context = LaunchContext {
+                            static_dir: settings.static_dir.clone(),
+                            players: request_body.players.clone(),
+                        };
+
+                        let problems = router.game_problems(&settings, &request_body.id, true)?;
+                        if !problems.is_empty() {
+                            return Response::builder()
+                                .status(StatusCode::SERVICE_UNAVAILABLE)
+                                .body(Body::from(format!(
+                                    "Game is unavailable: {}",
+                                    problems.join("; ")
+                                )))
+                                .map_err(|err| {
+                                    io::Error::new(
+                                        ErrorKind::Other,
+                                        format!(
+                                            "An error occured when building a response: {}",
+                                            err
+                                        ),
+                                    )
+                                });
+                        }
+
+                        println!("Starting game: {}", request_body.id);
+                        let launched =
+                            launcher::build_launch(game, &context, &settings.launch_defaults)
+                                .and_then(|launch| supervisor.launch(&request_body.id, launch));
+                        if launched.is_ok() {
+                            for player in &request_body.players {
+                                supervisor.leave_waitlist(player)?;
+                            }
+                        }
+
+                        let (status, body) = match launched {
+                            Ok(()) => (StatusCode::OK, "Starting game!".to_owned()),
+                            Err(ref err) if err.kind() == ErrorKind::AlreadyExists => {
+                                (StatusCode::CONFLICT, format!("{}", err))
+                            }
+                            Err(err) => {
+                                println!("Failed to start game {}: {}", request_body.id, err);
+                                (
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                    format!("Failed to start game: {}", err),
+                                )
+                            }
+                        };
+
+                        Response::builder()
+                            .status(status)
+                            .body(Body::from(body))
+                            .map_err(|err| {
+                                io::Error::new(
+                                    ErrorKind::Other,
+                                    format!("An error occured when building a response: {}", err),
+                                )
+                            })
+                    })
+                })
+            });
+        Box::new(response)
+    }
+
+    fn running_game(&self) -> ResponseFuture {
+        match self
+            .supervisor
+            .state()
+            .and_then(|state| {
+                serde_json::to_string(&state).map_err(|err| io::Error::new(ErrorKind::Other, err))
+            }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    fn stop_game(&self) -> ResponseFuture {
+        match self
+            .supervisor
+            .stop()
+            .and_then(|state| {
+                serde_json::to_string(&state).map_err(|err| io::Error::new(ErrorKind::Other, err))
+            }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    // Lists supervisor events newer than the `since` query parameter
+    fn events(&self, request: &Request<Body>) -> ResponseFuture {
+        let since = query_param(request, "since")
+            .and_then(|since| since.parse::<u64>().ok())
+            .unwrap_or(0);
+
+        match self.supervisor.events(since).and_then(|events| {
+            serde_json::to_string(&events).map_err(|err| io::Error::new(ErrorKind::Other, err))
+        }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    fn launches(&self) -> ResponseFuture {
+        match self.supervisor.launches().and_then(|launches| {
+            serde_json::to_string(&launches).map_err(|err| io::Error::new(ErrorKind::Other, err))
+        }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    // Serves the captured output of /api/v1/launches/{id}/log
+    fn launch_log(&self, path: &str) -> ResponseFuture {
+        let launch_id = path
+            .strip_prefix("/api/v1/launches/")
+            .and_then(|rest| rest.strip_suffix("/log"))
+            .and_then(|id| id.parse::<u64>().ok());
+
+        let (status, body) = match launch_id.map(|id| self.supervisor.launch_output(id)) {
+            Some(Ok(log)) => (StatusCode::OK, log),
+            Some(Err(ref err)) if err.kind() != ErrorKind::NotFound => {
+                return Box::new(future::err(io::Error::new(err.kind(), format!("{}", err))));
+            }
+            _ => (StatusCode::NOT_FOUND, "No log for this launch".to_owned()),
+        };
+
+        Box::new(future::result(
+            Response::builder()
+                .status(status)
+                .header(hyper::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(Body::from(body))
+                .map_err(|err| {
                     io::Error::new(
                         ErrorKind::Other,
-                        "Failed to acquire mutex lock on games list".to_owned(),
+                        format!("An error occured when building a response: {}", err),
                     )
-                });
+                }),
+        ))
+    }
 
-                games.map(|games| {
-                    let game = games.get(&request_body.id).ok_or_else(|| {
+    // Plays and minutes per game between the `from` and `to` query
+    // parameters, as JSON or as CSV with `format=csv`
+    fn stats(&self, request: &Request<Body>) -> ResponseFuture {
+        let range = time_param(request, "from", history::parse_time).and_then(|from| {
+            time_param(request, "to", history::parse_end_time)
+                .map(|to| (from.unwrap_or(0), to.unwrap_or(u64::max_value())))
+        });
+        let csv = query_param(request, "format").map_or(false, |format| format == "csv");
+
+        let stats = range.and_then(|(from, to)| {
+            self.supervisor.history().and_then(|history| match history {
+                Some(history) => history.stats(from, to),
+                None => Ok(Vec::new()),
+            })
+        });
+
+        let (status, content_type, body) = match stats {
+            Ok(ref stats) if csv => (StatusCode::OK, "text/csv", history::stats_to_csv(stats)),
+            Ok(stats) => match serde_json::to_string(&stats) {
+                Ok(json) => (StatusCode::OK, "application/json", json),
+                Err(err) => return Box::new(future::err(io::Error::new(ErrorKind::Other, err))),
+            },
+            Err(ref err) if err.kind() == ErrorKind::InvalidInput => (
+                error_status(err),
+                "application/json",
+                json!({ "success": false, "error": format!("{}", err) }).to_string(),
+            ),
+            Err(err) => return Box::new(future::err(err)),
+        };
+
+        Box::new(future::result(
+            Response::builder()
+                .status(status)
+                .header(hyper::header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .map_err(|err| {
+                    io::Error::new(
+                        ErrorKind::Other,
+                        format!("An error occured when building a response: {}", err),
+                    )
+                }),
+        ))
+    }
+
+    // Re-checks every game's executable and thumbnail
+    fn validate_games(&self) -> ResponseFuture {
+        let problems = self.update_settings(|settings| {
+            settings.problems = validate::check_games(&settings.games, &settings.static_dir);
+            print_problems(&settings.problems);
+            settings.problems.clone()
+        });
+
+        match problems.and_then(|problems| {
+            serde_json::to_string(&problems).map_err(|err| io::Error::new(ErrorKind::Other, err))
+        }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    fn reload(&self) -> ResponseFuture {
+        match self.reload_config() {
+            Ok(()) => self.json_response(StatusCode::OK, r#"{"success":true}"#.to_owned()),
+            Err(err) => self.json_response(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "success": false, "error": format!("{}", err) }).to_string(),
+            ),
+        }
+    }
+
+    // POST creates a game, PUT replaces one and DELETE removes one. Changes
+    // are saved to the config file, or to the game's file in games_dir.
+    fn edit_game(&self, request: Request<Body>) -> ResponseFuture {
+        let router = self.clone();
+        let method = request.method().clone();
+        let id = request
+            .uri()
+            .path()
+            .trim_start_matches("/api/v1/games/")
+            .to_owned();
+
+        let response = request
+            .into_body()
+            .concat2()
+            .map_err(|err| {
+                io::Error::new(
+                    ErrorKind::Other,
+                    format!("Failed to parse byte string: {}", err),
+                )
+            })
+            .and_then(move |body| {
+                let result = if !config::is_valid_game_id(&id) {
+                    Err(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Invalid game ID {:?}: use letters, digits, _ and -", id),
+                    ))
+                } else if method == Method::DELETE {
+                    router.delete_game(&id).map(|status| (status, Vec::new()))
+                } else {
+                    serde_json::from_slice(&body)
+                        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+                        .and_then(|game| router.save_game(&id, game, method == Method::POST))
+                };
+
+                let (status, body) = match result {
+                    Ok((status, warnings)) => (
+                        status,
+                        json!({ "success": true, "id": id, "warnings": warnings }),
+                    ),
+                    Err(err) => (
+                        error_status(&err),
+                        json!({ "success": false, "error": format!("{}", err) }),
+                    ),
+                };
+
+                Response::builder()
+                    .status(status)
+                    .header(hyper::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))
+                    .map_err(|err| {
                         io::Error::new(
                             ErrorKind::Other,
-                            "Failed to find game in list of available games".to_owned(),
+                            format!("An error occured when building a response: {}", err),
                         )
-                    })?;
+                    })
+            });
 
-                    let exe_path = game.exe_path.clone();
+        Box::new(response)
+    }
 
-                    /*
-                     *
-                     * Skipping additional arguments for now
-                     */
+    // Creates the game when `create` is set and replaces an existing one
+    // otherwise. Returns the problems that would keep the game from
+    // launching: its files may well be copied over after it is added, so
+    // they are warnings rather than reasons to refuse it.
+    fn save_game(
+        &self,
+        id: &str,
+        game: Game,
+        create: bool,
+    ) -> Result<(StatusCode, Vec<String>), io::Error> {
+        let static_dir = self.settings()?.static_dir.clone();
+        let warnings = validate::check_game(&game, &static_dir);
 
-                    //let exe_args = game.exe_args.clone();
-                    //Command::new(exe_path).args(exe_args).spawn()?;
+        self.edit_config(|config, config_path| {
+            match (create, config.games.contains_key(id)) {
+                (true, true) => Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("A game with ID {:?} already exists", id),
+                )),
+                (false, false) => Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("No game with ID {:?}", id),
+                )),
+                _ => config.save_game(config_path, id, game),
+            }
+        })?;
 
-                    println!("Starting game: {}", request_body.id);
-                    Command::new(exe_path.clone())
-                        .current_dir(exe_path.parent().unwrap())
-                        .spawn()?;
+        println!("Saved game {:?}", id);
+        for warning in &warnings {
+            println!("games.{}: {}", id, warning);
+        }
+        let status = if create {
+            StatusCode::CREATED
+        } else {
+            StatusCode::OK
+        };
+        Ok((status, warnings))
+    }
 
-                    Response::builder()
-                        .status(StatusCode::OK)
-                        .body(Body::from("Starting game!".to_owned()))
-                        .map_err(|err| {
-                            io::Error::new(
-                                ErrorKind::Other,
-                                format!("An error occured when building a response: {}", err),
-                            )
-                        })
-                })
+    fn delete_game(&self, id: &str) -> Result<StatusCode, io::Error> {
+        self.edit_config(|config, config_path| match config.remove_game(config_path, id)? {
+            Some(_) => Ok(()),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("No game with ID {:?}", id),
+            )),
+        })?;
+
+        println!("Deleted game {:?}", id);
+        Ok(StatusCode::OK)
+    }
+
+    // Lists the games installed in a Steam directory or, when `merge` is
+    // set, adds the chosen ones to the config
+    fn import_steam(&self, request: Request<Body>, merge: bool) -> ResponseFuture {
+        let router = self.clone();
+        let response = request
+            .into_body()
+            .concat2()
+            .map_err(|err| {
+                io::Error::new(
+                    ErrorKind::Other,
+                    format!("Failed to parse byte string: {}", err),
+                )
             })
-            .flatten();
+            .and_then(move |body| {
+                let result = serde_json::from_slice(&body)
+                    .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+                    .and_then(|import: SteamImportRequest| {
+                        if merge {
+                            router.merge_steam_games(&import)
+                        } else {
+                            router.steam_games(&import.steam_dir)
+                        }
+                    });
+
+                let (status, body) = match result {
+                    Ok(body) => (StatusCode::OK, body),
+                    Err(err) => (
+                        error_status(&err),
+                        json!({ "success": false, "error": format!("{}", err) }),
+                    ),
+                };
+
+                Response::builder()
+                    .status(status)
+                    .header(hyper::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))
+                    .map_err(|err| {
+                        io::Error::new(
+                            ErrorKind::Other,
+                            format!("An error occured when building a response: {}", err),
+                        )
+                    })
+            });
+
         Box::new(response)
     }
 
-    // Checks password at demo screen
-    // If correct, returns serialized access key in the ResponseFuture
-    fn check_password(
-        &mut self,
-        request: Request<Body>,
-        hashed_password: String,
-    ) -> ResponseFuture {
-        let access_key = self.access_key.clone();
+    fn steam_games(&self, steam_dir: &Path) -> Result<serde_json::Value, io::Error> {
+        let found = steam::find_games(steam_dir)?;
+        let settings = self.settings()?;
+        let listings: Vec<SteamListing> = found
+            .iter()
+            .map(|steam_game| SteamListing {
+                steam_game,
+                conflict: config::merge_conflict(&settings.games, &steam_game.id, &steam_game.game),
+            })
+            .collect();
+        Ok(json!({ "success": true, "games": listings }))
+    }
+
+    fn merge_steam_games(&self, import: &SteamImportRequest) -> Result<serde_json::Value, io::Error> {
+        let found = steam::find_games(&import.steam_dir)?;
+        for id in &import.ids {
+            if !found.iter().any(|steam_game| &steam_game.id == id) {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("No installed Steam game has the ID {:?}", id),
+                ));
+            }
+        }
+        let selected = found
+            .into_iter()
+            .filter(|steam_game| import.ids.contains(&steam_game.id))
+            .map(|steam_game| (steam_game.id, steam_game.game))
+            .collect();
+
+        let report = self.edit_config(|config, config_path| {
+            config.merge_launchable_games(config_path, selected)
+        })?;
+        println!("Imported Steam games: {:?}", report.added);
+        Ok(json!({ "success": true, "added": report.added, "skipped": report.skipped }))
+    }
+
+    fn game_health(&self) -> ResponseFuture {
+        match self.supervisor.health().and_then(|health| {
+            serde_json::to_string(&health).map_err(|err| io::Error::new(ErrorKind::Other, err))
+        }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    // Marks a game that crashed repeatedly as available again
+    fn clear_game_health(&self, request: Request<Body>) -> ResponseFuture {
+        let router = self.clone();
 
         let response = request
             .into_body()
@@ -285,50 +1042,79 @@ impl Router {
             .and_then(|body| {
                 serde_json::from_slice(&body).map_err(|err| io::Error::new(ErrorKind::Other, err))
             })
-            .and_then(move |request_body: PasswordRequest| {
-                let password = request_body.password.clone();
-
-                // 64 random bytes encoded as hex and stored as a string
-                let mut session_token = [0u8; 64];
-                rand::thread_rng().fill(&mut session_token[..]);
-                let session_token = hex::encode(&session_token[..]);
+            .and_then(move |request_body: ClearHealthRequest| {
+                let id = request_body.id;
+                // Games removed from the config may still have crashes to clear
+                let result = router.supervisor.clear_health(&id).and_then(|cleared| {
+                    if cleared || router.settings()?.games.contains_key(&id) {
+                        Ok(())
+                    } else {
+                        Err(io::Error::new(
+                            ErrorKind::NotFound,
+                            format!("No game with ID {:?}", id),
+                        ))
+                    }
+                });
+                let (status, body) = match result {
+                    Ok(()) => (StatusCode::OK, json!({ "success": true })),
+                    Err(err) => (
+                        error_status(&err),
+                        json!({ "success": false, "error": format!("{}", err) }),
+                    ),
+                };
 
-                let outgoing_json: String =
-                    if verify(&password, &hashed_password).map_err(|err| {
+                Response::builder()
+                    .status(status)
+                    .header(hyper::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))
+                    .map_err(|err| {
                         io::Error::new(
                             ErrorKind::Other,
-                            format!("Failed to parse BcryptResult: {}", err),
+                            format!("An error occured when building a response: {}", err),
                         )
-                    })? {
-                        let mut guard = access_key.lock().map_err(|err| {
-                            io::Error::new(
-                                ErrorKind::Other,
-                                format!("Failed to acquire mutex on games list: {}", err),
-                            )
-                        })?;
+                    })
+            });
 
-                        *guard = Some(AccessKey::new(
-                            session_token.clone(),
-                            SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .map_err(|err| {
-                                    io::Error::new(
-                                        ErrorKind::Other,
-                                        format!("Time went backwards: {}", err),
-                                    )
-                                })?
-                                .as_secs(),
-                        ));
-                        r#"{"success":true}"#.to_owned()
-                    } else {
-                        r#"{"success":false}"#.to_owned()
-                    };
+        Box::new(response)
+    }
+
+    fn waitlist(&self) -> ResponseFuture {
+        match self.supervisor.waitlist().and_then(|waitlist| {
+            serde_json::to_string(&waitlist).map_err(|err| io::Error::new(ErrorKind::Other, err))
+        }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    fn update_waitlist(&self, request: Request<Body>, join: bool) -> ResponseFuture {
+        let supervisor = self.supervisor.clone();
+
+        let response = request
+            .into_body()
+            .concat2()
+            .map_err(|err| {
+                io::Error::new(
+                    ErrorKind::Other,
+                    format!("Failed to parse byte string: {}", err),
+                )
+            })
+            .and_then(|body| {
+                serde_json::from_slice(&body).map_err(|err| io::Error::new(ErrorKind::Other, err))
+            })
+            .and_then(move |request_body: WaitlistRequest| {
+                let waitlist = if join {
+                    supervisor.join_waitlist(&request_body.name)?
+                } else {
+                    supervisor.leave_waitlist(&request_body.name)?
+                };
+                let body = serde_json::to_string(&waitlist)
+                    .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
 
                 Response::builder()
                     .status(StatusCode::OK)
-                    .header(SET_COOKIE, session_token.clone())
-                    .header("sam-token", session_token)
-                    .body(Body::from(outgoing_json))
+                    .header(hyper::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))
                     .map_err(|err| {
                         io::Error::new(
                             ErrorKind::Other,
@@ -340,40 +1126,220 @@ impl Router {
         Box::new(response)
     }
 
-    fn check_header(&self, request: &Request<Body>) -> Result<bool, io::Error> {
-        let access_key: String = self
-            .access_key
-            .lock()
+    // Refuses a login attempt from a client that has failed too often
+    fn too_many_attempts(&self, retry_after: u64) -> Result<Response<Body>, io::Error> {
+        Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .header(RETRY_AFTER, retry_after.to_string().as_str())
+            .body(Body::from(
+                json!({
+                    "success": false,
+                    "error": "Too many failed logins, try again later",
+                    "retry_after": retry_after,
+                })
+                .to_string(),
+            ))
             .map_err(|err| {
                 io::Error::new(
                     ErrorKind::Other,
-                    format!("Failed to acquire mutex on access key: {}", err),
+                    format!("An error occured when building a response: {}", err),
                 )
-            })?
-            .clone()
-            .unwrap_or_else(AccessKey::dummy)
-            .access_key;
+            })
+    }
 
-        request
+    // Replaces the stored password hash after a login with a weaker one.
+    // Nothing is replaced if the password was changed in the meantime.
+    fn store_rehashed_password(&self, old_hash: &str, new_hash: String) -> Result<(), io::Error> {
+        self.edit_config(|config, config_path| {
+            if config
+                .auth
+                .password_hash
+                .as_ref()
+                .map_or(true, |hash| hash != old_hash)
+            {
+                return Ok(());
+            }
+            config.auth.password_hash = Some(new_hash);
+            config.write_to_path(config_path)
+        })
+    }
+
+    // Checks password at demo screen
+    // If correct, starts a new session and returns its token in the response.
+    // Clients that keep failing have to wait longer and longer between tries.
+    fn check_password(&mut self, request: Request<Body>, settings: &Settings) -> ResponseFuture {
+        let hashed_password = settings.password.clone();
+        let policy = settings.session_policy;
+        let secure_cookie = settings.secure_cookie;
+        let throttle_policy = settings.throttle_policy;
+        let hash_policy = settings.hash_policy;
+
+        let address = self
+            .client
+            .map_or_else(|| "unknown".to_owned(), |ip| ip.to_string());
+        let router = self.clone();
+        let refuser = self.clone();
+        let sessions = self.sessions.clone();
+        let throttle = self.throttle.clone();
+        let client = request
             .headers()
-            .clone()
-            .get(COOKIE)
-            .ok_or_else(|| {
-                io::Error::new(ErrorKind::Other, "Failed to acquire cookie from header")
-            })?
-            .to_str()
+            .get(USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .map(str::to_owned);
+
+        let response = request
+            .into_body()
+            .concat2()
             .map_err(|err| {
                 io::Error::new(
                     ErrorKind::Other,
-                    format!("Failed to convert cookie to string: {}", err),
+                    format!("Failed to parse byte string: {}", err),
                 )
-            })?
-            .to_string()
-            .split("=")
-            .skip(1)
-            .next()
-            .map(|cookie| cookie == access_key)
-            .ok_or_else(|| io::Error::new(ErrorKind::Other, "Failed to acquire cookie from header"))
+            })
+            .and_then(|body| {
+                serde_json::from_slice(&body).map_err(|err| io::Error::new(ErrorKind::Other, err))
+            })
+            // Hashing, the throttle and its audit log all stay off the reactor
+            .and_then(move |request_body: PasswordRequest| {
+                blocking::run(move || {
+                    let attempt =
+                        match throttle.begin_attempt(&address, &throttle_policy, unix_time())? {
+                            Ok(attempt) => attempt,
+                            Err(retry_after) => return Ok(Login::Throttled(retry_after)),
+                        };
+
+                    // Without a password set, nobody can log in
+                    let verified = if hashed_password.is_empty() {
+                        Verified::Wrong
+                    } else {
+                        password::check_password(
+                            &request_body.password,
+                            &hashed_password,
+                            &hash_policy,
+                        )?
+                    };
+                    if let Verified::Rehashed(new_hash) = verified.clone() {
+                        // The login still succeeds if the new hash can't be saved
+                        match router.store_rehashed_password(&hashed_password, new_hash) {
+                            Ok(()) => {
+                                println!("Rehashed the password to the configured work factor")
+                            }
+                            Err(err) => println!("Failed to store the rehashed password: {}", err),
+                        }
+                    }
+
+                    if verified == Verified::Wrong {
+                        throttle.failure(&address, &throttle_policy, unix_time())?;
+                        Ok(Login::Wrong)
+                    } else {
+                        throttle.success(&address, attempt)?;
+                        Ok(Login::Correct)
+                    }
+                })
+            })
+            .and_then(move |login| {
+                let mut response = Response::builder();
+                response.status(StatusCode::OK);
+                let outgoing_json = match login {
+                    Login::Throttled(retry_after) => return refuser.too_many_attempts(retry_after),
+                    Login::Wrong => r#"{"success":false}"#,
+                    Login::Correct => {
+                        let session_token = sessions.create(&policy, client, unix_time())?;
+                        response.header(
+                            SET_COOKIE,
+                            sessions::session_cookie(
+                                SESSION_COOKIE,
+                                &session_token,
+                                &policy,
+                                secure_cookie,
+                            )
+                            .as_str(),
+                        );
+                        r#"{"success":true}"#
+                    }
+                };
+
+                response.body(Body::from(outgoing_json)).map_err(|err| {
+                    io::Error::new(
+                        ErrorKind::Other,
+                        format!("An error occured when building a response: {}", err),
+                    )
+                })
+            });
+
+        Box::new(response)
+    }
+
+    fn check_header(&self, request: &Request<Body>) -> Result<bool, io::Error> {
+        let token = session_token(request)?;
+        let settings = self.settings()?;
+        self.sessions.check(&token, &settings.session_policy, unix_time())
+    }
+
+    // Ends the session making the request and removes its cookie
+    fn logout(&self, request: &Request<Body>) -> ResponseFuture {
+        let removed = session_token(request)
+            .and_then(|token| self.sessions.remove(&token))
+            .unwrap_or(false);
+        let secure_cookie = self
+            .settings()
+            .map(|settings| settings.secure_cookie)
+            .unwrap_or(false);
+
+        Box::new(future::result(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .header(SET_COOKIE, sessions::expired_cookie(SESSION_COOKIE, secure_cookie).as_str())
+                .body(Body::from(json!({ "success": removed }).to_string()))
+                .map_err(|err| {
+                    io::Error::new(
+                        ErrorKind::Other,
+                        format!("An error occured when building a response: {}", err),
+                    )
+                }),
+        ))
+    }
+
+    fn list_sessions(&self, request: &Request<Body>) -> ResponseFuture {
+        let listings = self.settings().and_then(|settings| {
+            let current = match session_token(request) {
+                Ok(token) => self.sessions.id_of(&token)?,
+                Err(_) => None,
+            };
+            let sessions = self.sessions.list(&settings.session_policy, unix_time())?;
+            Ok(sessions
+                .into_iter()
+                .map(|session| SessionListing {
+                    current: current.as_ref() == Some(&session.id),
+                    session,
+                })
+                .collect::<Vec<SessionListing>>())
+        });
+        match listings.and_then(|listings| {
+            serde_json::to_string(&listings).map_err(|err| io::Error::new(ErrorKind::Other, err))
+        }) {
+            Ok(body) => self.json_response(StatusCode::OK, body),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    // Logs out another device, or this one
+    fn revoke_session(&self, path: &str) -> ResponseFuture {
+        let id = path.trim_start_matches("/api/v1/sessions/");
+        match self.sessions.revoke(id) {
+            Ok(true) => {
+                println!("Revoked session {}", id);
+                self.json_response(StatusCode::OK, r#"{"success":true}"#.to_owned())
+            }
+            Ok(false) => self.json_response(
+                StatusCode::NOT_FOUND,
+                json!({ "success": false, "error": format!("No session with ID {:?}", id) })
+                    .to_string(),
+            ),
+            Err(err) => Box::new(future::err(err)),
+        }
     }
 
     fn serve_static_file(
@@ -416,8 +1382,11 @@ impl Router {
     }
 
     fn route(&mut self, request: Request<Body>) -> ResponseFuture {
-        let root_dir: PathBuf = self.static_dir.clone();
-        let salted_hash: String = self.password.clone();
+        let settings = match self.settings() {
+            Ok(settings) => settings,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let root_dir: PathBuf = settings.static_dir.clone();
         let valid_files: Vec<PathBuf> = match list_files(root_dir.clone()) {
             Ok(v) => v,
             Err(_err) => vec![PathBuf::from("404.html")],
@@ -436,14 +1405,92 @@ impl Router {
         );
 
         match (request.method(), request.uri().path(), correct_cookie) {
-            (&Method::GET, "/api/v1/list_games", true) => self.list_games(),
+            (&Method::GET, "/api/v1/list_games", true) => self.list_games(&request),
             (&Method::GET, "/api/v1/list_games", false) => self.api_fail(),
 
             (&Method::POST, "/api/v1/start_game", true) => self.start_game(request),
             (&Method::POST, "/api/v1/start_game", false) => self.api_fail(),
 
+            (&Method::GET, "/api/v1/running_game", true) => self.running_game(),
+            (&Method::GET, "/api/v1/running_game", false) => self.api_fail(),
+
+            (&Method::POST, "/api/v1/stop_game", true) => self.stop_game(),
+            (&Method::POST, "/api/v1/stop_game", false) => self.api_fail(),
+
+            (&Method::GET, "/api/v1/events", true) => self.events(&request),
+            (&Method::GET, "/api/v1/events", false) => self.api_fail(),
+
+            (&Method::GET, "/api/v1/launches", true) => self.launches(),
+            (&Method::GET, "/api/v1/launches", false) => self.api_fail(),
+
+            (&Method::GET, path, true)
+                if path.starts_with("/api/v1/launches/") && path.ends_with("/log") =>
+            {
+                self.launch_log(path)
+            }
+            (&Method::GET, path, false)
+                if path.starts_with("/api/v1/launches/") && path.ends_with("/log") =>
+            {
+                self.api_fail()
+            }
+
+            (&Method::POST, "/api/v1/reload_config", true) => self.reload(),
+            (&Method::POST, "/api/v1/reload_config", false) => self.api_fail(),
+
+            (&Method::POST, "/api/v1/validate_games", true) => self.validate_games(),
+            (&Method::POST, "/api/v1/validate_games", false) => self.api_fail(),
+
+            (&Method::POST, path, true)
+            | (&Method::PUT, path, true)
+            | (&Method::DELETE, path, true)
+                if path.starts_with("/api/v1/games/") =>
+            {
+                self.edit_game(request)
+            }
+            (&Method::POST, path, false)
+            | (&Method::PUT, path, false)
+            | (&Method::DELETE, path, false)
+                if path.starts_with("/api/v1/games/") =>
+            {
+                self.api_fail()
+            }
+
+            (&Method::POST, "/api/v1/import/steam", true) => self.import_steam(request, false),
+            (&Method::POST, "/api/v1/import/steam/merge", true) => self.import_steam(request, true),
+            (&Method::POST, "/api/v1/import/steam", false)
+            | (&Method::POST, "/api/v1/import/steam/merge", false) => self.api_fail(),
+
+            (&Method::GET, "/api/v1/game_health", true) => self.game_health(),
+            (&Method::GET, "/api/v1/game_health", false) => self.api_fail(),
+
+            (&Method::POST, "/api/v1/clear_game_health", true) => self.clear_game_health(request),
+            (&Method::POST, "/api/v1/clear_game_health", false) => self.api_fail(),
+
+            (&Method::GET, "/api/v1/stats", true) => self.stats(&request),
+            (&Method::GET, "/api/v1/stats", false) => self.api_fail(),
+
+            (&Method::GET, "/api/v1/waitlist", true) => self.waitlist(),
+            (&Method::GET, "/api/v1/waitlist", false) => self.api_fail(),
+
+            (&Method::POST, "/api/v1/waitlist/join", true) => self.update_waitlist(request, true),
+            (&Method::POST, "/api/v1/waitlist/leave", true) => {
+                self.update_waitlist(request, false)
+            }
+            (&Method::POST, "/api/v1/waitlist/join", false)
+            | (&Method::POST, "/api/v1/waitlist/leave", false) => self.api_fail(),
+
             (&Method::POST, "/api/v1/check_password", _) => {
-                self.check_password(request, salted_hash)
+                self.check_password(request, &settings)
+            }
+            (&Method::POST, "/api/v1/logout", _) => self.logout(&request),
+
+            (&Method::GET, "/api/v1/sessions", true) => self.list_sessions(&request),
+            (&Method::GET, "/api/v1/sessions", false) => self.api_fail(),
+            (&Method::DELETE, path, true) if path.starts_with("/api/v1/sessions/") => {
+                self.revoke_session(path)
+            }
+            (&Method::DELETE, path, false) if path.starts_with("/api/v1/sessions/") => {
+                self.api_fail()
             }
 
             (&Method::GET, "/games.html", false) => self.redirect_endpoint(),
@@ -0,0 +1,260 @@
+// Logged in sessions, one per device.
+//
+// Every successful login gets its own random token, so logging in on a
+// second device leaves the first one logged in. A session ends when it has
+// been idle for too long, when it reaches its maximum age, on logout, or
+// when it is revoked through the admin API. Tokens never leave the store
+// except to the client that logged in; sessions are listed and revoked by a
+// separate public ID.
+//
+// Browsers get the token in an HttpOnly cookie. Other clients can read it
+// from the Set-Cookie header and send it back as `Authorization: Bearer`.
+
+use hyper::header::{HeaderMap, AUTHORIZATION, COOKIE};
+use rand::Rng;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub const SESSION_COOKIE: &str = "arclightning_session";
+// Cookies are shared between ports on the same host, so the web app's
+// sessions use a cookie of their own
+pub const WEB_SESSION_COOKIE: &str = "arclightning_web_session";
+
+// How long sessions last, from [auth] in the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionPolicy {
+    // Seconds without a request before a session ends
+    pub idle_secs: u64,
+    // Seconds after login a session ends, however active it is
+    pub max_age_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created: u64,
+    pub last_seen: u64,
+    // When the session ends if it stays idle
+    pub expires: u64,
+    // The user agent that logged in, when known
+    pub client: Option<String>,
+    // The account signed in to, for web app sessions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+impl SessionInfo {
+    fn is_expired(&self, policy: &SessionPolicy, now: u64) -> bool {
+        now >= self.last_seen.saturating_add(policy.idle_secs)
+            || now >= self.created.saturating_add(policy.max_age_secs)
+    }
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut random = vec![0u8; bytes];
+    rand::thread_rng().fill(&mut random[..]);
+    hex::encode(&random)
+}
+
+// Shared by every clone, like the supervisor
+#[derive(Debug, Clone, Default)]
+pub struct Sessions {
+    // Keyed by token
+    sessions: Arc<Mutex<HashMap<String, SessionInfo>>>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Sessions::default()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<HashMap<String, SessionInfo>>, io::Error> {
+        self.sessions.lock().map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on sessions: {}", err),
+            )
+        })
+    }
+
+    // Starts a session and returns its token
+    pub fn create(
+        &self,
+        policy: &SessionPolicy,
+        client: Option<String>,
+        now: u64,
+    ) -> Result<String, io::Error> {
+        self.start(policy, None, client, now)
+    }
+
+    // Starts a session signed in to the account `user`
+    pub fn create_for_user(
+        &self,
+        policy: &SessionPolicy,
+        user: &str,
+        client: Option<String>,
+        now: u64,
+    ) -> Result<String, io::Error> {
+        self.start(policy, Some(user.to_owned()), client, now)
+    }
+
+    fn start(
+        &self,
+        policy: &SessionPolicy,
+        user: Option<String>,
+        client: Option<String>,
+        now: u64,
+    ) -> Result<String, io::Error> {
+        let token = random_hex(64);
+        let session = SessionInfo {
+            id: random_hex(8),
+            created: now,
+            last_seen: now,
+            expires: now.saturating_add(policy.idle_secs.min(policy.max_age_secs)),
+            client,
+            user,
+        };
+        self.lock()?.insert(token.clone(), session);
+        Ok(token)
+    }
+
+    // Whether `token` belongs to a live session. Using a session keeps it
+    // from going idle; an expired one is removed.
+    pub fn check(&self, token: &str, policy: &SessionPolicy, now: u64) -> Result<bool, io::Error> {
+        let mut sessions = self.lock()?;
+        let expired = match sessions.get_mut(token) {
+            None => return Ok(false),
+            Some(session) => {
+                if !session.is_expired(policy, now) {
+                    session.last_seen = now;
+                    session.expires = now
+                        .saturating_add(policy.idle_secs)
+                        .min(session.created.saturating_add(policy.max_age_secs));
+                }
+                session.is_expired(policy, now)
+            }
+        };
+        if expired {
+            sessions.remove(token);
+        }
+        Ok(!expired)
+    }
+
+    // The public ID of the session with `token`
+    pub fn id_of(&self, token: &str) -> Result<Option<String>, io::Error> {
+        Ok(self.lock()?.get(token).map(|session| session.id.clone()))
+    }
+
+    // The account the session with `token` is signed in to
+    pub fn user_of(&self, token: &str) -> Result<Option<String>, io::Error> {
+        Ok(self
+            .lock()?
+            .get(token)
+            .and_then(|session| session.user.clone()))
+    }
+
+    // Ends the session with `token`. Returns whether there was one.
+    pub fn remove(&self, token: &str) -> Result<bool, io::Error> {
+        Ok(self.lock()?.remove(token).is_some())
+    }
+
+    // Ends the session with the public ID `id`. Returns whether there was one.
+    pub fn revoke(&self, id: &str) -> Result<bool, io::Error> {
+        let mut sessions = self.lock()?;
+        let before = sessions.len();
+        sessions.retain(|_, session| session.id != id);
+        Ok(sessions.len() != before)
+    }
+
+    // Removes every expired session and returns how many there were
+    pub fn purge(&self, policy: &SessionPolicy, now: u64) -> Result<usize, io::Error> {
+        let mut sessions = self.lock()?;
+        let before = sessions.len();
+        sessions.retain(|_, session| !session.is_expired(policy, now));
+        Ok(before - sessions.len())
+    }
+
+    // Live sessions, oldest first
+    pub fn list(&self, policy: &SessionPolicy, now: u64) -> Result<Vec<SessionInfo>, io::Error> {
+        let mut sessions: Vec<SessionInfo> = self
+            .lock()?
+            .values()
+            .filter(|session| !session.is_expired(policy, now))
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+        Ok(sessions)
+    }
+}
+
+// The Set-Cookie value that hands `token` to a browser in the cookie
+// `name`. `secure` keeps the cookie off plain HTTP connections.
+pub fn session_cookie(name: &str, token: &str, policy: &SessionPolicy, secure: bool) -> String {
+    let mut cookie = format!(
+        "{}={}; HttpOnly; SameSite=Strict; Path=/; Max-Age={}",
+        name, token, policy.max_age_secs
+    );
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+// The Set-Cookie value that removes the session cookie `name` on logout
+pub fn expired_cookie(name: &str, secure: bool) -> String {
+    session_cookie(
+        name,
+        "",
+        &SessionPolicy {
+            idle_secs: 0,
+            max_age_secs: 0,
+        },
+        secure,
+    )
+}
+
+// The value of the cookie called `name` in a Cookie header such as
+// `theme=dark; arclightning_session=abc`
+pub fn cookie_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header
+        .split(';')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((key.trim(), value.trim())),
+                _ => None,
+            }
+        })
+        .find(|&(key, _)| key == name)
+        .map(|(_, value)| value.trim_matches('"'))
+}
+
+// The token in an `Authorization: Bearer <token>` header
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let mut parts = header.trim().splitn(2, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("bearer") => {
+            Some(token.trim()).filter(|token| !token.is_empty())
+        }
+        _ => None,
+    }
+}
+
+// The session token a request carries in the cookie `cookie_name` or, for
+// clients without cookies, in its `Authorization: Bearer` header
+pub fn request_token(headers: &HeaderMap, cookie_name: &str) -> Option<String> {
+    let from_cookie = headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .filter_map(|header| cookie_value(header, cookie_name))
+        .next();
+    let from_bearer = || {
+        headers
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(bearer_token)
+    };
+    from_cookie.or_else(from_bearer).map(str::to_owned)
+}
@@ -0,0 +1,263 @@
+// Imports installed Steam games from a local Steam directory.
+//
+// Steam keeps its library folders in steamapps/libraryfolders.vdf and one
+// steamapps/appmanifest_<appid>.acf per installed app, both in Valve's KeyValues
+// ("VDF") text format. Imported games are launched through Steam with a
+// steam://rungameid/<appid> URL.
+
+use config::{suggested_game_id, Game};
+use launcher::STEAM_RUN_PREFIX;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+// A VDF value: either a string or a list of key/value pairs. Keys can
+// repeat, so objects keep every pair in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Vdf {
+    Value(String),
+    Object(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    // The first value under `key`. Keys are case insensitive in VDF.
+    pub fn get(&self, key: &str) -> Option<&Vdf> {
+        match *self {
+            Vdf::Object(ref pairs) => pairs
+                .iter()
+                .find(|&&(ref name, _)| name.eq_ignore_ascii_case(key))
+                .map(|&(_, ref value)| value),
+            Vdf::Value(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Vdf::Value(ref value) => Some(value),
+            Vdf::Object(_) => None,
+        }
+    }
+
+    pub fn pairs(&self) -> &[(String, Vdf)] {
+        match *self {
+            Vdf::Object(ref pairs) => pairs,
+            Vdf::Value(_) => &[],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some(escaped) => text.push(escaped),
+                            None => return Err("unterminated escape".to_owned()),
+                        },
+                        Some(c) => text.push(c),
+                        None => return Err("unterminated string".to_owned()),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                // Comments run to the end of the line
+                while chars.peek().map_or(false, |&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '[' => {
+                // Platform conditionals such as [$WIN32] are ignored
+                while chars.next().map_or(false, |c| c != ']') {}
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut text = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+                        break;
+                    }
+                    text.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Text(text));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_pairs<I>(tokens: &mut I, nested: bool) -> Result<Vec<(String, Vdf)>, String>
+where
+    I: Iterator<Item = Token>,
+{
+    let mut pairs = Vec::new();
+    loop {
+        let key = match tokens.next() {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) if nested => return Ok(pairs),
+            None if !nested => return Ok(pairs),
+            Some(Token::Close) => return Err("unexpected }".to_owned()),
+            Some(Token::Open) => return Err("expected a key, found {".to_owned()),
+            None => return Err("missing }".to_owned()),
+        };
+        let value = match tokens.next() {
+            Some(Token::Text(value)) => Vdf::Value(value),
+            Some(Token::Open) => Vdf::Object(parse_pairs(tokens, true)?),
+            _ => return Err(format!("missing value for key {:?}", key)),
+        };
+        pairs.push((key, value));
+    }
+}
+
+// Parses a VDF document into its top level object
+pub fn parse(source: &str) -> Result<Vdf, String> {
+    let mut tokens = tokenize(source)?.into_iter();
+    parse_pairs(&mut tokens, false).map(Vdf::Object)
+}
+
+fn load(path: &Path) -> Result<Vdf, io::Error> {
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+    parse(&source)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+}
+
+// Every Steam library, starting with the one inside `steam_dir`
+pub fn library_folders(steam_dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut folders = vec![steam_dir.to_path_buf()];
+
+    let vdf_path = steam_dir.join("steamapps").join("libraryfolders.vdf");
+    let vdf = match load(&vdf_path) {
+        Ok(vdf) => vdf,
+        Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(folders),
+        Err(err) => return Err(err),
+    };
+
+    // Older clients list bare paths, newer ones an object with a "path"
+    let root = vdf.get("libraryfolders").or_else(|| vdf.get("LibraryFolders"));
+    for &(ref key, ref value) in root.map(Vdf::pairs).unwrap_or(&[]) {
+        if !key.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let path = value
+            .as_str()
+            .or_else(|| value.get("path").and_then(Vdf::as_str));
+        if let Some(path) = path {
+            let path = PathBuf::from(path);
+            if !folders.contains(&path) {
+                folders.push(path);
+            }
+        }
+    }
+    Ok(folders)
+}
+
+// Redistributables and compatibility tools that show up as installed apps
+fn is_tool(app_id: u32, name: &str) -> bool {
+    app_id == 228_980
+        || name.starts_with("Proton ")
+        || name.starts_with("Steam Linux Runtime")
+        || name.starts_with("Steamworks")
+}
+
+// An installed Steam game, as it would be added to the config
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SteamGame {
+    pub id: String,
+    pub app_id: u32,
+    pub install_dir: PathBuf,
+    pub game: Game,
+}
+
+fn steam_game(library: &Path, manifest: &Vdf) -> Option<SteamGame> {
+    let app = manifest.get("AppState")?;
+    let app_id: u32 = app.get("appid")?.as_str()?.parse().ok()?;
+    let name = app.get("name")?.as_str()?.to_owned();
+    let install_dir = library
+        .join("steamapps")
+        .join("common")
+        .join(app.get("installdir")?.as_str()?);
+    if is_tool(app_id, &name) {
+        return None;
+    }
+    // Names without ASCII letters or digits fall back to the app ID
+    let id = match suggested_game_id(&name) {
+        ref id if id.is_empty() => format!("steam_{}", app_id),
+        id => id,
+    };
+
+    Some(SteamGame {
+        id,
+        app_id,
+        game: Game {
+            name,
+            exe_path: PathBuf::from(format!("{}{}", STEAM_RUN_PREFIX, app_id)),
+            working_dir: Some(install_dir.clone()),
+            ..Default::default()
+        },
+        install_dir,
+    })
+}
+
+// Every game installed in any library of `steam_dir`, sorted by name.
+// Manifests that can't be read are skipped with a message.
+pub fn find_games(steam_dir: &Path) -> Result<Vec<SteamGame>, io::Error> {
+    if !steam_dir.join("steamapps").is_dir() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("{} has no steamapps directory", steam_dir.display()),
+        ));
+    }
+
+    let mut games: Vec<SteamGame> = Vec::new();
+    for library in library_folders(steam_dir)? {
+        let entries = match fs::read_dir(library.join("steamapps")) {
+            Ok(entries) => entries,
+            Err(err) => {
+                println!("Skipping Steam library {}: {}", library.display(), err);
+                continue;
+            }
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if !file_name.starts_with("appmanifest_") || !file_name.ends_with(".acf") {
+                continue;
+            }
+            match load(&path) {
+                Ok(manifest) => {
+                    if let Some(game) = steam_game(&library, &manifest) {
+                        if !games.iter().any(|other| other.app_id == game.app_id) {
+                            games.push(game);
+                        }
+                    }
+                }
+                Err(err) => println!("Skipping {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    games.sort_by(|a, b| a.game.name.cmp(&b.game.name));
+    Ok(games)
+}
@@ -0,0 +1,756 @@
+use config::write_atomically;
+use history::{PlayHistory, PlayRecord};
+use hooks::{self, HookCommand};
+use launch_log::{unix_time, LaunchLog, LaunchRecord};
+use launcher::{Launch, Relaunch};
+use process;
+use serde_json;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the watcher thread checks whether the game has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Time a game gets to exit after SIGTERM before it is killed
+const DEFAULT_KILL_GRACE: Duration = Duration::from_secs(5);
+
+// Only the most recent events are kept for clients polling /api/v1/events
+const MAX_EVENTS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum GameState {
+    Idle,
+    Launching { id: String },
+    Running {
+        id: String,
+        pid: u32,
+        launch_id: Option<u64>,
+    },
+    // Asked to exit, and killed if it hasn't within the kill grace
+    Stopping { id: String },
+    // Handed off to another program, such as Steam, which runs the game
+    // out of our sight. It can't be stopped, timed or told apart from a
+    // crash, and doesn't keep other games from launching.
+    Detached { id: String },
+    Exited { id: String, code: Option<i32> },
+}
+
+impl GameState {
+    pub fn is_active(&self) -> bool {
+        match *self {
+            GameState::Launching { .. }
+            | GameState::Running { .. }
+            | GameState::Stopping { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventKind {
+    GameStarted { id: String },
+    GameExited { id: String, code: Option<i32> },
+    SessionWarning { id: String, seconds_left: u64 },
+    SessionExpired { id: String },
+    GameCrashed {
+        id: String,
+        code: Option<i32>,
+        consecutive: u32,
+        retrying: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Event {
+    pub seq: u64,
+    pub time: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+// How long a single launch may run before it is terminated
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionLimit {
+    pub max: Duration,
+    // Players are warned this long before the game is terminated
+    pub warning: Duration,
+    // Only enforce the limit while someone is on the waitlist
+    pub only_when_waiting: bool,
+}
+
+// When an exit counts as a crash, and what happens after one
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrashPolicy {
+    // Exiting sooner than this after launch counts as a crash, even with
+    // a zero exit code
+    pub grace: Duration,
+    // Launch the game once more after a crash
+    pub retry: bool,
+    // Consecutive crashes before a game is marked unhealthy
+    pub max_consecutive: u32,
+}
+
+impl Default for CrashPolicy {
+    fn default() -> Self {
+        CrashPolicy {
+            grace: Duration::from_secs(5),
+            retry: false,
+            max_consecutive: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct GameHealth {
+    pub consecutive_crashes: u32,
+    pub total_crashes: u32,
+    pub last_crash: Option<u64>,
+    // Set after too many consecutive crashes, until an admin clears it
+    pub unhealthy: bool,
+}
+
+#[derive(Debug)]
+struct Session {
+    limit: SessionLimit,
+    deadline: Instant,
+    warned: bool,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: GameState,
+    child: Option<Child>,
+    // Run by whoever notices the game has ended
+    post_exit: Vec<HookCommand>,
+    session: Option<Session>,
+    log: Option<LaunchLog>,
+    record: Option<LaunchRecord>,
+    history: Option<PlayHistory>,
+    // Start time and players of the running game, for the play history
+    started: u64,
+    players: Vec<String>,
+    launched_at: Instant,
+    crash_policy: CrashPolicy,
+    kill_grace: Duration,
+    // Set once the game was asked to exit; it is killed after this
+    kill_deadline: Option<Instant>,
+    killed: bool,
+    health: HashMap<String, GameHealth>,
+    // Where `health` is kept, so unhealthy games stay that way after a
+    // restart
+    health_path: Option<PathBuf>,
+    // Set for a launch that may still be retried after a crash
+    relaunch: Option<Relaunch>,
+    // Incremented on every launch so a watcher never touches a newer game
+    generation: u64,
+    events: VecDeque<Event>,
+    next_seq: u64,
+    waitlist: Vec<String>,
+}
+
+impl Inner {
+    fn save_health(&self) {
+        let path = match self.health_path {
+            Some(ref path) => path,
+            None => return,
+        };
+        let saved = serde_json::to_string_pretty(&self.health)
+            .map_err(|err| io::Error::new(ErrorKind::Other, err))
+            .and_then(|json| write_atomically(path, &json));
+        if let Err(err) = saved {
+            println!("Failed to save game health to {:?}: {}", path, err);
+        }
+    }
+
+    fn push_event(&mut self, kind: EventKind) {
+        self.next_seq += 1;
+        self.events.push_back(Event {
+            seq: self.next_seq,
+            time: unix_time(),
+            kind,
+        });
+        while self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+
+    // Records that the running game ended and starts its post-exit hooks.
+    // `stopped` is set when the game was ended on purpose rather than exiting
+    // by itself. Returns the launch to retry if the game crashed.
+    fn finish(&mut self, status: ExitStatus, stopped: bool) -> Option<Relaunch> {
+        let ended = unix_time();
+        let too_quick = self.launched_at.elapsed() < self.crash_policy.grace;
+        let crashed = !stopped && (!status.success() || too_quick);
+        let mut relaunch = self.relaunch.take();
+
+        self.child = None;
+        self.session = None;
+        self.kill_deadline = None;
+        self.killed = false;
+        if let Some(mut record) = self.record.take() {
+            record.ended = Some(ended);
+            record.code = status.code();
+            self.save_record(&record);
+        }
+        if let Some(id) = running_id(&self.state) {
+            println!("Game exited: {} ({})", id, status);
+            if let Some(ref history) = self.history {
+                let play = PlayRecord {
+                    game_id: id.clone(),
+                    started: self.started,
+                    ended,
+                    duration_secs: ended.saturating_sub(self.started),
+                    code: status.code(),
+                    players: mem::replace(&mut self.players, Vec::new()),
+                };
+                if let Err(err) = history.append(&play) {
+                    println!("Failed to record play of {}: {}", id, err);
+                }
+            }
+
+            if crashed {
+                let max_consecutive = self.crash_policy.max_consecutive;
+                let consecutive = {
+                    let health = self.health.entry(id.clone()).or_insert_with(GameHealth::default);
+                    health.consecutive_crashes += 1;
+                    health.total_crashes += 1;
+                    health.last_crash = Some(ended);
+                    if health.consecutive_crashes >= max_consecutive {
+                        health.unhealthy = true;
+                    }
+                    health.consecutive_crashes
+                };
+                if consecutive >= max_consecutive || !self.crash_policy.retry {
+                    relaunch = None;
+                }
+                println!("Game crashed: {} ({} in a row)", id, consecutive);
+                self.push_event(EventKind::GameCrashed {
+                    id: id.clone(),
+                    code: status.code(),
+                    consecutive,
+                    retrying: relaunch.is_some(),
+                });
+            } else {
+                relaunch = None;
+                if let Some(health) = self.health.get_mut(&id) {
+                    health.consecutive_crashes = 0;
+                }
+            }
+            if self.health.contains_key(&id) {
+                self.save_health();
+            }
+
+            self.push_event(EventKind::GameExited {
+                id: id.clone(),
+                code: status.code(),
+            });
+            self.state = GameState::Exited {
+                id,
+                code: status.code(),
+            };
+        }
+        run_post_exit(mem::replace(&mut self.post_exit, Vec::new()));
+        relaunch
+    }
+
+    fn save_record(&self, record: &LaunchRecord) {
+        if let Some(ref log) = self.log {
+            if let Err(err) = log.save(record) {
+                println!("Failed to save launch record {}: {}", record.id, err);
+            }
+        }
+    }
+
+    // Asks the running game and everything it started to exit, if any. The
+    // watcher kills whatever is left once kill_grace has passed, so this
+    // never waits for the game.
+    fn terminate(&mut self) -> Result<(), io::Error> {
+        if self.kill_deadline.is_some() {
+            return Ok(());
+        }
+        match self.child {
+            Some(ref mut child) => process::request_exit(child)?,
+            None => return Ok(()),
+        }
+        self.kill_deadline = Some(Instant::now() + self.kill_grace);
+        self.session = None;
+        if let Some(id) = running_id(&self.state) {
+            self.state = GameState::Stopping { id };
+        }
+        Ok(())
+    }
+
+    // Kills the game if it is still running kill_grace after terminate
+    fn escalate(&mut self) -> Result<(), io::Error> {
+        let overdue = self
+            .kill_deadline
+            .map_or(false, |deadline| Instant::now() >= deadline);
+        if !overdue || self.killed {
+            return Ok(());
+        }
+        if let Some(ref mut child) = self.child {
+            println!("Process group {} ignored SIGTERM, killing it", child.id());
+            process::force_exit(child)?;
+        }
+        self.killed = true;
+        Ok(())
+    }
+
+    // The exit status of the game once its whole process group has exited
+    fn poll(&mut self) -> Result<Option<ExitStatus>, io::Error> {
+        match self.child {
+            Some(ref mut child) => match child.try_wait()? {
+                Some(status) if !process::group_alive(child) => Ok(Some(status)),
+                _ => Ok(None),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Warns about and then enforces the session limit of the running game
+    fn check_session(&mut self) -> Result<(), io::Error> {
+        let now = Instant::now();
+        let enforce = !self.waitlist.is_empty();
+        let (warn, expire) = match self.session {
+            Some(ref mut session) => {
+                if session.limit.only_when_waiting && !enforce {
+                    (None, false)
+                } else if !session.warned && now + session.limit.warning >= session.deadline {
+                    session.warned = true;
+                    // Someone who joins the waitlist after the deadline
+                    // still gets the full warning period
+                    if session.deadline < now + session.limit.warning {
+                        session.deadline = now + session.limit.warning;
+                    }
+                    (Some(session.deadline - now), false)
+                } else {
+                    (None, session.warned && now >= session.deadline)
+                }
+            }
+            None => (None, false),
+        };
+
+        if let Some(id) = running_id(&self.state) {
+            if let Some(left) = warn {
+                println!("Session for {} ends in {}s", id, left.as_secs());
+                self.push_event(EventKind::SessionWarning {
+                    id,
+                    seconds_left: left.as_secs(),
+                });
+            } else if expire {
+                println!("Session for {} expired", id);
+                self.push_event(EventKind::SessionExpired { id });
+                self.terminate()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Owns the process of the game currently running on the cabinet.
+// Only one game may be launching or running at a time.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor::with_log(None)
+    }
+
+    // Game output and launch records are written to `log` when it is set
+    pub fn with_log(log: Option<LaunchLog>) -> Self {
+        Supervisor {
+            inner: Arc::new(Mutex::new(Inner {
+                state: GameState::Idle,
+                child: None,
+                post_exit: Vec::new(),
+                session: None,
+                log,
+                record: None,
+                history: None,
+                started: 0,
+                players: Vec::new(),
+                launched_at: Instant::now(),
+                crash_policy: CrashPolicy::default(),
+                kill_grace: DEFAULT_KILL_GRACE,
+                kill_deadline: None,
+                killed: false,
+                health: HashMap::new(),
+                health_path: None,
+                relaunch: None,
+                generation: 0,
+                events: VecDeque::new(),
+                next_seq: 0,
+                waitlist: Vec::new(),
+            })),
+        }
+    }
+
+    // Appends every finished game to `history`
+    pub fn with_history(self, history: PlayHistory) -> Self {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.history = Some(history);
+        }
+        self
+    }
+
+    // Keeps game health in `path`, starting from what is already there
+    pub fn with_health_file(self, path: PathBuf) -> Self {
+        let health = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                println!("Ignoring bad game health file {:?}: {}", path, err);
+                HashMap::new()
+            }),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                println!("Failed to read game health from {:?}: {}", path, err);
+                HashMap::new()
+            }
+        };
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.health = health;
+            inner.health_path = Some(path);
+        }
+        self
+    }
+
+    // How long a stopped game gets to exit before it is killed
+    pub fn with_kill_grace(self, kill_grace: Duration) -> Self {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.kill_grace = kill_grace;
+        }
+        self
+    }
+
+    pub fn with_crash_policy(self, crash_policy: CrashPolicy) -> Self {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.crash_policy = crash_policy;
+        }
+        self
+    }
+
+    fn lock(&self) -> Result<MutexGuard<Inner>, io::Error> {
+        self.inner.lock().map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on supervisor: {}", err),
+            )
+        })
+    }
+
+    pub fn state(&self) -> Result<GameState, io::Error> {
+        Ok(self.lock()?.state.clone())
+    }
+
+    // Events with a sequence number greater than `since`
+    pub fn events(&self, since: u64) -> Result<Vec<Event>, io::Error> {
+        Ok(self
+            .lock()?
+            .events
+            .iter()
+            .filter(|event| event.seq > since)
+            .cloned()
+            .collect())
+    }
+
+    pub fn launches(&self) -> Result<Vec<LaunchRecord>, io::Error> {
+        match self.lock()?.log.clone() {
+            Some(log) => log.list(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn launch_output(&self, launch_id: u64) -> Result<String, io::Error> {
+        match self.lock()?.log.clone() {
+            Some(log) => log.read_log(launch_id),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                "Launch logging is disabled".to_owned(),
+            )),
+        }
+    }
+
+    pub fn health(&self) -> Result<HashMap<String, GameHealth>, io::Error> {
+        Ok(self.lock()?.health.clone())
+    }
+
+    pub fn is_healthy(&self, id: &str) -> Result<bool, io::Error> {
+        Ok(self
+            .lock()?
+            .health
+            .get(id)
+            .map_or(true, |health| !health.unhealthy))
+    }
+
+    // Forgets the crashes of a game, making it available again. Returns
+    // whether there were any.
+    pub fn clear_health(&self, id: &str) -> Result<bool, io::Error> {
+        let mut inner = self.lock()?;
+        let cleared = inner.health.remove(id).is_some();
+        if cleared {
+            inner.save_health();
+        }
+        Ok(cleared)
+    }
+
+    pub fn history(&self) -> Result<Option<PlayHistory>, io::Error> {
+        Ok(self.lock()?.history.clone())
+    }
+
+    pub fn waitlist(&self) -> Result<Vec<String>, io::Error> {
+        Ok(self.lock()?.waitlist.clone())
+    }
+
+    pub fn join_waitlist(&self, name: &str) -> Result<Vec<String>, io::Error> {
+        let mut inner = self.lock()?;
+        if !inner.waitlist.iter().any(|waiting| waiting == name) {
+            inner.waitlist.push(name.to_owned());
+        }
+        Ok(inner.waitlist.clone())
+    }
+
+    pub fn leave_waitlist(&self, name: &str) -> Result<Vec<String>, io::Error> {
+        let mut inner = self.lock()?;
+        inner.waitlist.retain(|waiting| waiting != name);
+        Ok(inner.waitlist.clone())
+    }
+
+    // Runs the pre-launch hooks and spawns the game as the running game for
+    // `id`. Fails with ErrorKind::AlreadyExists if another game is still
+    // active; the game is not started if any pre-launch hook fails. Blocks
+    // until the hooks are done, so keep it off the reactor.
+    pub fn launch(&self, id: &str, mut launch: Launch) -> Result<(), io::Error> {
+        let generation = {
+            let mut inner = self.lock()?;
+            if inner.state.is_active() {
+                return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    "Another game is already running".to_owned(),
+                ));
+            }
+            inner.generation += 1;
+            inner.state = GameState::Launching { id: id.to_owned() };
+            inner.generation
+        };
+
+        if let Err(err) = hooks::run_all(&mut launch.pre_launch) {
+            self.lock()?.state = GameState::Idle;
+            return Err(io::Error::new(
+                err.kind(),
+                format!("Pre-launch hook failed: {}", err),
+            ));
+        }
+
+        let log = self.lock()?.log.clone();
+        let mut record = None;
+        // The program a detached launch hands off to may start in the same
+        // group, and must not be taken down with it
+        if !launch.detached {
+            process::new_process_group(&mut launch.command);
+        }
+        let spawned = match log.map(|log| log.start(id)) {
+            Some(Ok((new_record, file))) => {
+                record = Some(new_record);
+                file.try_clone().and_then(|stdout| {
+                    launch.command.stdout(Stdio::from(stdout));
+                    launch.command.stderr(Stdio::from(file));
+                    launch.command.spawn()
+                })
+            }
+            // A broken log directory shouldn't keep every game from starting
+            Some(Err(err)) => {
+                println!("Failed to start launch log for {}: {}", id, err);
+                launch.command.spawn()
+            }
+            None => launch.command.spawn(),
+        };
+
+        let mut inner = self.lock()?;
+        match spawned {
+            Ok(child) if launch.detached => {
+                inner.state = GameState::Detached { id: id.to_owned() };
+                inner.push_event(EventKind::GameStarted { id: id.to_owned() });
+                if !launch.post_exit.is_empty() {
+                    println!("Post-exit hooks don't run for detached launches of {}", id);
+                }
+                drop(inner);
+                self.reap_detached(id, generation, child, record);
+                Ok(())
+            }
+            Ok(child) => {
+                inner.state = GameState::Running {
+                    id: id.to_owned(),
+                    pid: child.id(),
+                    launch_id: record.as_ref().map(|record| record.id),
+                };
+                inner.child = Some(child);
+                inner.record = record;
+                inner.started = unix_time();
+                inner.players = launch.players;
+                inner.launched_at = Instant::now();
+                inner.relaunch = launch.relaunch;
+                inner.post_exit = launch.post_exit;
+                inner.session = launch.session_limit.map(|limit| Session {
+                    deadline: Instant::now() + limit.max,
+                    limit,
+                    warned: false,
+                });
+                inner.push_event(EventKind::GameStarted { id: id.to_owned() });
+                self.watch(generation);
+                Ok(())
+            }
+            Err(err) => {
+                inner.state = GameState::Idle;
+                if let Some(mut record) = record {
+                    record.ended = Some(unix_time());
+                    record.error = Some(format!("{}", err));
+                    inner.save_record(&record);
+                }
+                run_post_exit(launch.post_exit);
+                Err(err)
+            }
+        }
+    }
+
+    // Asks the running game, if any, to exit and returns the resulting
+    // state. The game is Stopping until its whole process group is gone.
+    pub fn stop(&self) -> Result<GameState, io::Error> {
+        let mut inner = self.lock()?;
+        inner.terminate()?;
+        Ok(inner.state.clone())
+    }
+
+    fn watch(&self, generation: u64) {
+        let supervisor = self.clone();
+        let inner = self.inner.clone();
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let mut inner = match inner.lock() {
+                Ok(guard) => guard,
+                Err(_err) => return,
+            };
+            if inner.generation != generation {
+                return;
+            }
+
+            if inner.child.is_none() {
+                // Game was already stopped
+                return;
+            }
+
+            let status = match inner.poll() {
+                Ok(Some(status)) => status,
+                Ok(None) => {
+                    if let Err(err) = inner.check_session() {
+                        println!("Failed to end session: {}", err);
+                    }
+                    if let Err(err) = inner.escalate() {
+                        println!("Failed to kill running game: {}", err);
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    println!("Failed to poll running game: {}", err);
+                    continue;
+                }
+            };
+
+            let id = running_id(&inner.state);
+            let stopped = inner.kill_deadline.is_some();
+            let relaunch = inner.finish(status, stopped);
+            drop(inner);
+
+            if let (Some(id), Some(relaunch)) = (id, relaunch) {
+                supervisor.retry(&id, &relaunch);
+            }
+            return;
+        });
+    }
+
+    // Waits for the command of a detached launch to hand the game off. The
+    // game itself isn't followed any further.
+    fn reap_detached(
+        &self,
+        id: &str,
+        generation: u64,
+        mut child: Child,
+        record: Option<LaunchRecord>,
+    ) {
+        let id = id.to_owned();
+        let inner = self.inner.clone();
+        thread::spawn(move || {
+            let status = child.wait();
+            let mut inner = match inner.lock() {
+                Ok(guard) => guard,
+                Err(_err) => return,
+            };
+            if let Some(mut record) = record {
+                record.ended = Some(unix_time());
+                match status {
+                    Ok(ref status) => record.code = status.code(),
+                    Err(ref err) => record.error = Some(format!("{}", err)),
+                }
+                inner.save_record(&record);
+            }
+
+            let code = match status {
+                Ok(ref status) if status.success() => return,
+                Ok(ref status) => status.code(),
+                Err(_) => None,
+            };
+            println!("Failed to hand off {}: {:?}", id, status);
+            if inner.generation == generation {
+                inner.push_event(EventKind::GameExited {
+                    id: id.clone(),
+                    code,
+                });
+                inner.state = GameState::Exited { id, code };
+            }
+        });
+    }
+
+    // Launches a crashed game once more. The retry itself is never retried.
+    fn retry(&self, id: &str, relaunch: &Relaunch) {
+        println!("Retrying crashed game: {}", id);
+        let launched = relaunch.build().and_then(|mut launch| {
+            launch.relaunch = None;
+            self.launch(id, launch)
+        });
+        if let Err(err) = launched {
+            println!("Failed to retry {}: {}", id, err);
+        }
+    }
+}
+
+fn running_id(state: &GameState) -> Option<String> {
+    match *state {
+        GameState::Launching { ref id }
+        | GameState::Running { ref id, .. }
+        | GameState::Stopping { ref id } => Some(id.clone()),
+        _ => None,
+    }
+}
+
+// Post-exit hooks run on their own thread so cleanup never holds up the API
+fn run_post_exit(mut post_exit: Vec<HookCommand>) {
+    if post_exit.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        if let Err(err) = hooks::run_all(&mut post_exit) {
+            println!("Post-exit hook failed: {}", err);
+        }
+    });
+}
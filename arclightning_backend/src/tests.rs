@@ -1,5 +1,6 @@
 #![cfg(test)]
-use config::{Config, Game};
+use config::{Auth, Config, Game};
+use migrate::CONFIG_VERSION;
 use password;
 use router::list_files;
 use std::collections::HashMap;
@@ -43,10 +44,11 @@ fn test_read_toml() {
         Game {
             name: "Touhou".to_owned(),
             description: "bullet hell with waifus".to_owned(),
-            genres: vec!["bullet hell".to_owned(), "anime".to_owned()],
+            tags: vec!["bullet hell".to_owned(), "anime".to_owned()],
             thumbnail_path: PathBuf::from(r"path\to\touhou\thumbnail"),
             exe_path: PathBuf::from(r"test_files\touhou_game.exe"),
             exe_args: vec!["arg1".to_owned(), "arg2".to_owned()],
+            ..Default::default()
         },
     );
 
@@ -55,10 +57,11 @@ fn test_read_toml() {
         Game {
             name: "Melty Blood".to_owned(),
             description: "fighter with waifus".to_owned(),
-            genres: vec!["fighter".to_owned(), "anime".to_owned(), "2d".to_owned()],
+            tags: vec!["fighter".to_owned(), "anime".to_owned(), "2d".to_owned()],
             thumbnail_path: PathBuf::from(r"path\to\melty_blood\thumbnail"),
             exe_path: PathBuf::from(r"test_files\melty_blood_game.exe"),
             exe_args: vec!["arg1".to_owned(), "arg2".to_owned()],
+            ..Default::default()
         },
     );
     assert_eq!(games, test_games);
@@ -79,13 +82,13 @@ fn test_json_serialization() {
     // test cases separately to get around the nondeterministic order for hashmap
     let test_json_touhou = "{\"name\":\"Touhou\",\
                             \"description\":\"bullet hell with waifus\",\
-                            \"genres\":[\"bullet hell\",\"anime\"],\
+                            \"tags\":[\"bullet hell\",\"anime\"],\
                             \"thumbnail_path\":\"path/to/touhou/thumbnail\",\
                             \"exe_path\":\"test_files\\\\touhou_game.exe\",\
                             \"exe_args\":[\"arg1\",\"arg2\"]}";
     let test_json_mb = "{\"name\":\"Melty Blood\",\
                         \"description\":\"fighter with waifus\",\
-                        \"genres\":[\"fighter\",\"anime\",\"2d\"],\
+                        \"tags\":[\"fighter\",\"anime\",\"2d\"],\
                         \"thumbnail_path\":\"path/to/melty_blood/thumbnail\",\
                         \"exe_path\":\"test_files\\\\melty_blood_game.exe\",\
                         \"exe_args\":[\"arg1\",\"arg2\"]}";
@@ -113,19 +116,91 @@ fn test_games_serialization() {
 
 #[test]
 fn test_check_password() {
-    // this still fails...?
-    let password: String = "this_IS my_P455W0RD!%".to_owned();
-    let hashed_password: Vec<u8> = "a50f985ce10f2dfbf71e119ae69\
-                                    522754b65e022c558d2ce9160df\
-                                    4113060eb66bf5de6e1ce400c05\
-                                    34a08db6916f4c2751353de29f8\
-                                    4608dd0ebe67e57e12e0"
-        .to_owned()
-        .into_bytes();
+    use password::{HashPolicy, Scheme, Verified};
 
-    println!("{:?}", hashed_password);
+    let weak = HashPolicy {
+        scheme: Scheme::Bcrypt,
+        bcrypt_cost: 4,
+        argon2_memory_kib: 64,
+        argon2_iterations: 1,
+    };
+    let password = "this_IS my_P455W0RD!%";
 
-    assert!(password::check_password(&password, &hashed_password));
+    let bcrypt_hash = password::hash(password, &weak).unwrap();
+    assert!(password::is_valid_hash(&bcrypt_hash));
+    assert!(password::verify(password, &bcrypt_hash).unwrap());
+    assert!(!password::verify("hunter2", &bcrypt_hash).unwrap());
+    assert!(password::verify(password, "not a hash").is_err());
+
+    let argon2 = HashPolicy {
+        scheme: Scheme::Argon2id,
+        ..weak
+    };
+    let argon2_hash = password::hash(password, &argon2).unwrap();
+    assert!(argon2_hash.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+    assert!(password::is_valid_hash(&argon2_hash));
+    assert!(password::verify(password, &argon2_hash).unwrap());
+    assert!(!password::verify("hunter2", &argon2_hash).unwrap());
+
+    // Hashes weaker than the policy, or made with another scheme, are
+    // replaced on a correct login
+    assert!(!password::needs_rehash(&bcrypt_hash, &weak));
+    assert!(password::needs_rehash(&bcrypt_hash, &HashPolicy { bcrypt_cost: 5, ..weak }));
+    assert!(password::needs_rehash(&bcrypt_hash, &argon2));
+    assert!(!password::needs_rehash(&argon2_hash, &argon2));
+    assert!(password::needs_rehash(
+        &argon2_hash,
+        &HashPolicy {
+            argon2_iterations: 2,
+            ..argon2
+        }
+    ));
+    assert!(!password::needs_rehash(&argon2_hash, &HashPolicy { argon2_memory_kib: 32, ..argon2 }));
+
+    assert_eq!(
+        password::check_password("hunter2", &bcrypt_hash, &argon2).unwrap(),
+        Verified::Wrong
+    );
+    assert_eq!(
+        password::check_password(password, &argon2_hash, &argon2).unwrap(),
+        Verified::Correct
+    );
+    match password::check_password(password, &bcrypt_hash, &argon2).unwrap() {
+        Verified::Rehashed(new_hash) => {
+            assert!(new_hash.starts_with("$argon2id$"));
+            assert!(password::verify(password, &new_hash).unwrap());
+        }
+        other => panic!("Expected a rehash, got {:?}", other),
+    }
+    // A policy that can't hash doesn't fail a correct login
+    let broken = HashPolicy {
+        bcrypt_cost: 40,
+        ..weak
+    };
+    assert_eq!(
+        password::check_password(password, &bcrypt_hash, &broken).unwrap(),
+        Verified::Correct
+    );
+
+    // Config::set_password uses the policy from [auth]
+    let mut config: Config = toml::from_str(
+        "listen_port = 3000\nstatic_dir = \"static\"\n\
+         [auth]\npassword_scheme = \"argon2id\"\nargon2_memory_kib = 64\nargon2_iterations = 1\n",
+    ).unwrap();
+    config.set_password(password).unwrap();
+    let stored = config.auth.password_hash.clone().unwrap();
+    assert_eq!(
+        password::check_password(password, &stored, &argon2).unwrap(),
+        Verified::Correct
+    );
+
+    // ...which is checked when the config is loaded
+    assert!(config.validate().is_ok());
+    config.auth.bcrypt_cost = 40;
+    assert_eq!(config.validate().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    config.auth.bcrypt_cost = 12;
+    config.auth.argon2_memory_kib = 4;
+    assert!(config.validate().is_err());
 }
 
 #[test]
@@ -139,3 +214,1477 @@ fn test_hexdump() {
     println!("{}", session_token);
     assert_eq!(hex::decode(session_token).unwrap().len(), 64);
 }
+
+// Waits for a stopped game's process group to be gone
+#[cfg(unix)]
+fn wait_for_exit(supervisor: &::supervisor::Supervisor) -> ::supervisor::GameState {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let started = Instant::now();
+    loop {
+        let state = supervisor.state().unwrap();
+        if !state.is_active() || started.elapsed() > Duration::from_secs(10) {
+            return state;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_supervisor_single_game() {
+    use launcher::Launch;
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    use supervisor::{GameState, Supervisor};
+
+    let supervisor = Supervisor::new();
+    assert_eq!(supervisor.state().unwrap(), GameState::Idle);
+
+    let mut command = Command::new("sleep");
+    command.arg("5");
+    supervisor.launch("sleepy", Launch::new(command)).unwrap();
+    assert!(supervisor.state().unwrap().is_active());
+
+    // A second launch is refused while the first game is running
+    let mut command = Command::new("sleep");
+    command.arg("5");
+    assert!(supervisor.launch("sleepy_2", Launch::new(command)).is_err());
+
+    // Stopping doesn't wait for the game to exit
+    assert_eq!(
+        supervisor.stop().unwrap(),
+        GameState::Stopping {
+            id: "sleepy".to_owned()
+        }
+    );
+    match wait_for_exit(&supervisor) {
+        GameState::Exited { id, .. } => assert_eq!(id, "sleepy"),
+        state => panic!("Unexpected state after stop: {:?}", state),
+    }
+
+    // A detached launch only hands the game off, so it isn't supervised
+    let mut launch = Launch::new(Command::new("true"));
+    launch.detached = true;
+    supervisor.launch("steamy", launch).unwrap();
+    let detached = GameState::Detached {
+        id: "steamy".to_owned(),
+    };
+    assert_eq!(supervisor.state().unwrap(), detached);
+    assert_eq!(supervisor.stop().unwrap(), detached);
+
+    // ...unless handing it off failed
+    let mut launch = Launch::new(Command::new("false"));
+    launch.detached = true;
+    supervisor.launch("steamy", launch).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    match supervisor.state().unwrap() {
+        GameState::Exited { id, code } => {
+            assert_eq!(id, "steamy");
+            assert_eq!(code, Some(1));
+        }
+        state => panic!("Unexpected state after failed hand off: {:?}", state),
+    }
+}
+
+#[test]
+fn test_exe_args_templates() {
+    use launcher::{check_template, expand, LaunchContext};
+
+    let game = Game {
+        name: "Melty Blood".to_owned(),
+        description: "fighter with waifus".to_owned(),
+        tags: vec![],
+        thumbnail_path: PathBuf::from("images/meltyblood.jpg"),
+        exe_path: PathBuf::from("games/mbaa/MBAA.exe"),
+        ..Default::default()
+    };
+    let context = LaunchContext {
+        static_dir: PathBuf::from("static"),
+        players: vec!["sam".to_owned(), "alex".to_owned()],
+    };
+
+    assert_eq!(
+        expand("--p1={player1_name} --p2={player2_name}", &game, &context).unwrap(),
+        "--p1=sam --p2=alex"
+    );
+    assert_eq!(expand("{player_count}", &game, &context).unwrap(), "2");
+    assert_eq!(expand("{player3_name}", &game, &context).unwrap(), "");
+    assert_eq!(
+        expand("{game_dir}", &game, &context).unwrap(),
+        PathBuf::from("games/mbaa").to_string_lossy()
+    );
+    assert_eq!(expand("{{literal}}", &game, &context).unwrap(), "{literal}");
+
+    let bare = Game {
+        exe_path: PathBuf::from("MBAA.exe"),
+        ..game.clone()
+    };
+    assert!(expand("{game_dir}", &bare, &context).is_err());
+
+    assert!(check_template("--netplay={static_dir}").is_ok());
+    assert!(check_template("{player0_name}").is_err());
+    assert!(check_template("{not_a_placeholder}").is_err());
+    assert!(check_template("{unclosed").is_err());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_failed_pre_launch_hook() {
+    use hooks::HookCommand;
+    use launcher::Launch;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+    use supervisor::{GameState, Supervisor};
+
+    let supervisor = Supervisor::new();
+    let mut launch = Launch::new(Command::new("sleep"));
+    launch.pre_launch.push(HookCommand {
+        command: Command::new("false"),
+        timeout: Duration::from_secs(5),
+    });
+
+    // The game is never started when a pre-launch hook fails
+    assert!(supervisor.launch("sleepy", launch).is_err());
+    assert_eq!(supervisor.state().unwrap(), GameState::Idle);
+
+    let mut hook = HookCommand {
+        command: Command::new("sleep"),
+        timeout: Duration::from_millis(100),
+    };
+    hook.command.arg("5");
+    assert!(hook.run().unwrap().timed_out);
+
+    // Processes the hook started are killed with it, and ones left running
+    // in the background don't hold up the hook
+    let started = Instant::now();
+    let mut hook = HookCommand {
+        command: Command::new("sh"),
+        timeout: Duration::from_millis(100),
+    };
+    hook.command.args(&["-c", "sleep 5 & sleep 5"]);
+    assert!(hook.run().unwrap().timed_out);
+    let mut hook = HookCommand {
+        command: Command::new("sh"),
+        timeout: Duration::from_secs(5),
+    };
+    hook.command.args(&["-c", "sleep 5 &"]);
+    assert!(hook.run().unwrap().success());
+    assert!(started.elapsed() < Duration::from_secs(4));
+}
+
+#[test]
+fn test_config_toml_round_trip() {
+    use config::Hook;
+
+    let mut env = HashMap::new();
+    env.insert("SDL_VIDEODRIVER".to_owned(), "x11".to_owned());
+
+    let mut games = HashMap::new();
+    games.insert(
+        "melty_blood".to_owned(),
+        Game {
+            name: "Melty Blood".to_owned(),
+            exe_path: PathBuf::from("games/mbaa/MBAA.exe"),
+            exe_args: vec!["{player1_name}".to_owned()],
+            env_clear: true,
+            env,
+            pre_launch: Some(vec![Hook {
+                command: PathBuf::from("mount_iso"),
+                args: vec!["{game_dir}".to_owned()],
+                timeout_secs: Some(10),
+            }]),
+            ..Default::default()
+        },
+    );
+    let config = Config {
+        config_version: CONFIG_VERSION,
+        listen_address: "::".parse().unwrap(),
+        listen_port: 3000,
+        web_listen_port: 3001,
+        static_dir: PathBuf::from("static"),
+        max_session_minutes: Some(30),
+        session_warning_minutes: 2,
+        limit_only_when_waiting: false,
+        log_dir: PathBuf::from("logs"),
+        max_launch_logs: 50,
+        history_path: PathBuf::from("play_history.jsonl"),
+        accounts_path: PathBuf::from("accounts.toml"),
+        crash_grace_secs: 5,
+        retry_on_crash: true,
+        max_consecutive_crashes: 3,
+        hide_unavailable_games: false,
+        kill_grace_secs: 5,
+        games_dir: Some(PathBuf::from("games.d")),
+        auth: Auth {
+            password_hash: Some("$2y$04$RNzRQu2G0CeZACdaT/XeeeWp/c2nSZwnC15C9sM3MULDuT0NHJ1o6".to_owned()),
+            ..Default::default()
+        },
+        pre_launch: vec![],
+        post_exit: vec![Hook {
+            command: PathBuf::from("reset_controllers"),
+            ..Default::default()
+        }],
+        games,
+        game_files: HashMap::new(),
+        config_dir: PathBuf::new(),
+    };
+
+    let toml_string = toml::to_string(&config).unwrap();
+    let parsed: Config = toml::from_str(&toml_string).unwrap();
+    assert_eq!(parsed, config);
+    assert!(parsed.validate().is_ok());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_session_limit() {
+    use launcher::{build_launch, Launch, LaunchContext, LaunchDefaults};
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    use supervisor::{EventKind, GameState, SessionLimit, Supervisor};
+
+    // A warning longer than the session is cut to half of it
+    let game = Game {
+        exe_path: PathBuf::from("/bin/true"),
+        max_session_minutes: Some(2),
+        ..Default::default()
+    };
+    let defaults = LaunchDefaults {
+        session_warning_minutes: 5,
+        ..Default::default()
+    };
+    let limit = build_launch(&game, &LaunchContext::default(), &defaults)
+        .unwrap()
+        .session_limit
+        .unwrap();
+    assert_eq!(limit.warning, Duration::from_secs(60));
+
+    let supervisor = Supervisor::new();
+    let mut launch = Launch::new(Command::new("sleep"));
+    launch.command.arg("30");
+    launch.session_limit = Some(SessionLimit {
+        max: Duration::from_millis(600),
+        warning: Duration::from_millis(300),
+        only_when_waiting: true,
+    });
+    supervisor.launch("sleepy", launch).unwrap();
+
+    // Nobody is waiting, so the limit is not enforced
+    thread::sleep(Duration::from_millis(1000));
+    assert!(supervisor.state().unwrap().is_active());
+
+    supervisor.join_waitlist("alex").unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    match supervisor.state().unwrap() {
+        GameState::Exited { id, .. } => assert_eq!(id, "sleepy"),
+        state => panic!("Unexpected state after session limit: {:?}", state),
+    }
+
+    let kinds: Vec<EventKind> = supervisor
+        .events(0)
+        .unwrap()
+        .into_iter()
+        .map(|event| event.kind)
+        .collect();
+    assert_eq!(kinds[0], EventKind::GameStarted { id: "sleepy".to_owned() });
+    match kinds[1] {
+        EventKind::SessionWarning { .. } => {}
+        ref kind => panic!("Expected a session warning, got {:?}", kind),
+    }
+    assert_eq!(kinds[2], EventKind::SessionExpired { id: "sleepy".to_owned() });
+}
+
+#[test]
+#[cfg(unix)]
+fn test_launch_log_capture() {
+    use launch_log::LaunchLog;
+    use launcher::Launch;
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    use supervisor::Supervisor;
+
+    let log_dir = env::temp_dir().join("arclightning_test_launch_log");
+    let _ = fs::remove_dir_all(&log_dir);
+    let supervisor = Supervisor::with_log(Some(LaunchLog::new(log_dir.clone(), 2)));
+
+    for _ in 0..3 {
+        let mut launch = Launch::new(Command::new("sh"));
+        launch.command.args(&["-c", "echo out; echo err >&2; exit 3"]);
+        supervisor.launch("echo", launch).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    // Only the newest two launches are kept
+    let launches = supervisor.launches().unwrap();
+    assert_eq!(launches.len(), 2);
+    assert_eq!(launches[0].game_id, "echo");
+    assert_eq!(launches[0].code, Some(3));
+    assert!(launches[0].ended.is_some());
+
+    let output = supervisor.launch_output(launches[0].id).unwrap();
+    assert!(output.contains("out"));
+    assert!(output.contains("err"));
+    fs::remove_dir_all(&log_dir).unwrap();
+
+    // Games still start when the log can't be written
+    fs::write(&log_dir, "not a directory").unwrap();
+    let supervisor = Supervisor::with_log(Some(LaunchLog::new(log_dir.clone(), 2)));
+    let mut launch = Launch::new(Command::new("sh"));
+    launch.command.args(&["-c", "exit 0"]);
+    supervisor.launch("echo", launch).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(!supervisor.state().unwrap().is_active());
+
+    fs::remove_file(&log_dir).unwrap();
+}
+
+#[test]
+fn test_play_history_stats() {
+    use history::{parse_end_time, parse_time, stats_to_csv, PlayHistory, PlayRecord};
+    use std::env;
+    use std::fs;
+
+    assert_eq!(parse_time("1970-01-02"), Some(86_400));
+    assert_eq!(parse_time("2018-10-18"), Some(1_539_820_800));
+    assert_eq!(parse_time("1539820800"), Some(1_539_820_800));
+    assert_eq!(parse_time("2018-13-01"), None);
+    assert_eq!(parse_time("2024-02-31"), None);
+    assert_eq!(parse_time("2023-02-29"), None);
+    assert!(parse_time("2024-02-29").is_some());
+    // A date ends a range at the end of that day
+    assert_eq!(parse_end_time("2018-10-18"), Some(1_539_820_800 + 86_400));
+    assert_eq!(parse_end_time("1539820800"), Some(1_539_820_800));
+
+    let path = env::temp_dir().join("arclightning_test_history.jsonl");
+    let _ = fs::remove_file(&path);
+    let history = PlayHistory::new(path.clone());
+
+    let plays = vec![("tekken_7", 1000, 600), ("tekken_7", 2000, 300), ("touhou", 3000, 120)];
+    for (game_id, started, duration_secs) in plays {
+        history
+            .append(&PlayRecord {
+                game_id: game_id.to_owned(),
+                started,
+                ended: started + duration_secs,
+                duration_secs,
+                code: Some(0),
+                players: vec![],
+            })
+            .unwrap();
+    }
+
+    let stats = history.stats(0, 2500).unwrap();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].game_id, "tekken_7");
+    assert_eq!(stats[0].plays, 2);
+    assert_eq!(stats[0].total_minutes, 15.0);
+
+    let csv = stats_to_csv(&history.stats(0, u64::max_value()).unwrap());
+    assert_eq!(
+        csv,
+        "game_id,plays,total_minutes\n\"tekken_7\",2,15.0\n\"touhou\",1,2.0\n"
+    );
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_crash_detection() {
+    use launcher::{build_launch, LaunchContext, LaunchDefaults};
+    use std::env;
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+    use supervisor::{CrashPolicy, Supervisor};
+
+    let health_path = env::temp_dir().join("arclightning_test_game_health.json");
+    let _ = fs::remove_file(&health_path);
+    let supervisor = Supervisor::new()
+        .with_health_file(health_path.clone())
+        .with_crash_policy(CrashPolicy {
+            grace: Duration::from_secs(5),
+            retry: true,
+            max_consecutive: 3,
+        });
+    let game = Game {
+        name: "Crashy".to_owned(),
+        exe_path: PathBuf::from("/bin/false"),
+        ..Default::default()
+    };
+    let launch = build_launch(&game, &LaunchContext::default(), &LaunchDefaults::default());
+
+    // The first launch crashes and is retried once, which crashes again
+    supervisor.launch("crashy", launch.unwrap()).unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    let health = supervisor.health().unwrap()["crashy"].clone();
+    assert_eq!(health.consecutive_crashes, 2);
+    assert!(!health.unhealthy);
+
+    let launch = build_launch(&game, &LaunchContext::default(), &LaunchDefaults::default());
+    supervisor.launch("crashy", launch.unwrap()).unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    assert!(!supervisor.is_healthy("crashy").unwrap());
+
+    // Unhealthy games stay that way after a restart
+    let restarted = Supervisor::new().with_health_file(health_path.clone());
+    assert!(!restarted.is_healthy("crashy").unwrap());
+    assert_eq!(restarted.clear_health("crashy").unwrap(), true);
+    assert!(restarted.is_healthy("crashy").unwrap());
+    assert_eq!(restarted.clear_health("crashy").unwrap(), false);
+    let restarted = Supervisor::new().with_health_file(health_path.clone());
+    assert!(restarted.is_healthy("crashy").unwrap());
+
+    supervisor.clear_health("crashy").unwrap();
+    assert!(supervisor.is_healthy("crashy").unwrap());
+    fs::remove_file(&health_path).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_process_group_lifetime() {
+    use launcher::Launch;
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    use supervisor::{GameState, Supervisor};
+
+    let supervisor = Supervisor::new().with_kill_grace(Duration::from_secs(1));
+
+    // Like a launcher stub: start the real game in the background and exit
+    let mut launch = Launch::new(Command::new("sh"));
+    launch.command.args(&["-c", "sleep 30 & exit 0"]);
+    supervisor.launch("launcher", launch).unwrap();
+
+    thread::sleep(Duration::from_millis(1000));
+    assert!(supervisor.state().unwrap().is_active());
+
+    // Stopping the game takes down the background process too
+    supervisor.stop().unwrap();
+    match wait_for_exit(&supervisor) {
+        GameState::Exited { id, .. } => assert_eq!(id, "launcher"),
+        state => panic!("Unexpected state after stop: {:?}", state),
+    }
+
+    // A game that ignores SIGTERM is killed once the grace has passed
+    let mut launch = Launch::new(Command::new("sh"));
+    launch
+        .command
+        .args(&["-c", "trap '' TERM; sleep 30 & sleep 30"]);
+    supervisor.launch("stubborn", launch).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    supervisor.stop().unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(supervisor.state().unwrap().is_active());
+    match wait_for_exit(&supervisor) {
+        GameState::Exited { id, .. } => assert_eq!(id, "stubborn"),
+        state => panic!("Unexpected state after stop: {:?}", state),
+    }
+}
+
+#[test]
+fn test_validate_game() {
+    use std::env;
+    use validate::check_game;
+
+    let static_dir = env::current_dir().unwrap().join("test_files");
+    let mut game = Game {
+        name: "Touhou".to_owned(),
+        thumbnail_path: PathBuf::from("test_games.json"),
+        exe_path: env::current_exe().unwrap(),
+        ..Default::default()
+    };
+    assert!(check_game(&game, &static_dir).is_empty());
+
+    // Games don't need a thumbnail
+    game.thumbnail_path = PathBuf::new();
+    assert!(check_game(&game, &static_dir).is_empty());
+
+    game.thumbnail_path = PathBuf::from("../server_config.toml");
+    assert_eq!(check_game(&game, &static_dir).len(), 1);
+
+    game.exe_path = PathBuf::from("D:\\Steam\\steamapps\\common\\missing.exe");
+    game.thumbnail_path = PathBuf::from("missing.png");
+    assert_eq!(check_game(&game, &static_dir).len(), 2);
+
+    // Bare program names are looked up in the PATH the game gets
+    #[cfg(unix)]
+    {
+        game.thumbnail_path = PathBuf::new();
+        game.exe_path = PathBuf::from("sh");
+        assert!(check_game(&game, &static_dir).is_empty());
+        game.exe_path = PathBuf::from("arclightning_no_such_program");
+        assert_eq!(check_game(&game, &static_dir).len(), 1);
+        game.exe_path = PathBuf::from("sh");
+        game.env_clear = true;
+        assert_eq!(check_game(&game, &static_dir).len(), 1);
+        game.env.insert("PATH".to_owned(), "/usr/bin:/bin".to_owned());
+        assert!(check_game(&game, &static_dir).is_empty());
+    }
+}
+
+#[test]
+fn test_check_config_diagnostics() {
+    use check::check_source;
+    use std::path::Path;
+
+    let path = Path::new("server_config.toml");
+
+    let source = "config_version = 2\nlisten_port = 3000\nstatic_dir = \"test_files\"\n\n\
+                  [games.touhou]\nname = \"Touhou\"\n";
+    let diagnostics = check_source(path, source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].line, Some(5));
+    assert!(diagnostics[0].message.contains("missing field"));
+
+    let source = "config_version = 2\nlisten_port = 3000\nstatic_dir = \"test_files\"\n\
+                  [auth]\n\
+                  password_hash = \"hunter2\"\n\
+                  [games.touhou]\n\
+                  name = \"Touhou\"\n\
+                  description = \"\"\n\
+                  tags = []\n\
+                  thumbnail_path = \"test_games.json\"\n\
+                  exe_path = \"missing.exe\"\n\
+                  exe_args = []\n";
+    let diagnostics = check_source(path, source);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].line, Some(5));
+    assert_eq!(diagnostics[1].line, Some(11));
+    assert!(diagnostics[1].message.starts_with("games.touhou: exe_path"));
+
+    let diagnostics = check_source(path, "config_version = 2\nlisten_port = 70000\n");
+    assert_eq!(diagnostics[0].line, Some(2));
+
+    // Every broken game is reported, not just the first
+    let source = "config_version = 2\nlisten_port = 3000\nstatic_dir = \"test_files\"\n\n\
+                  [games.touhou]\nname = \"Touhou\"\n\
+                  [games.bbtag]\nname = \"BBTAG\"\n";
+    let diagnostics = check_source(path, source);
+    assert_eq!(diagnostics.len(), 2);
+    let lines: Vec<_> = diagnostics.iter().map(|diagnostic| diagnostic.line).collect();
+    assert!(lines.contains(&Some(5)) && lines.contains(&Some(7)));
+
+    // static_dir is relative to the config file
+    let source = "config_version = 2\nlisten_port = 3000\nstatic_dir = \"test_files\"\n";
+    assert!(check_source(path, source).is_empty());
+    let nested = Path::new("test_files").join("server_config.toml");
+    assert_eq!(check_source(&nested, source).len(), 1);
+}
+
+#[test]
+fn test_games_dir() {
+    use check::check_file;
+    use std::env;
+    use std::fs;
+
+    let dir = env::temp_dir().join("arclightning_test_games_dir");
+    let games_dir = dir.join("games.d");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&games_dir).unwrap();
+
+    let game = "name = \"Cave Story\"\ndescription = \"\"\ntags = []\n\
+                thumbnail_path = \"cave.png\"\nexe_path = \"cave/Doukutsu.exe\"\n\
+                exe_args = []\n";
+    fs::write(games_dir.join("cave_story.toml"), game).unwrap();
+    fs::write(games_dir.join("README.md"), "not a game").unwrap();
+
+    let config_path = dir.join("server_config.toml");
+    let inline = format!(
+        "config_version = 2\nlisten_port = 3000\nstatic_dir = \"static\"\n\
+         games_dir = {:?}\n\n[auth]\npassword_hash = \"hunter2\"\n\n[games.touhou]\n{}",
+        games_dir, game
+    );
+    fs::write(&config_path, &inline).unwrap();
+
+    let mut config = Config::load(&config_path).unwrap();
+    assert_eq!(config.games.len(), 2);
+    assert_eq!(config.games["cave_story"].name, "Cave Story");
+    assert!(config.game_files.contains_key("cave_story"));
+
+    // Writing the config back leaves drop-in games in their own files
+    config.auth.password_hash = None;
+    config.write_to_path(&config_path).unwrap();
+    let written = fs::read_to_string(&config_path).unwrap();
+    assert!(written.contains("[games.touhou]"));
+    assert!(!written.contains("cave_story"));
+    assert_eq!(Config::load(&config_path).unwrap().games.len(), 2);
+
+    // A game defined in both places is a conflict, which doesn't keep the
+    // other games from loading
+    fs::write(games_dir.join("touhou.toml"), game).unwrap();
+    let mut config: Config = toml::from_str(&inline).unwrap();
+    let conflicts = config.load_games_dir(&config_path).unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert!(conflicts[0].contains("`touhou`"));
+    let config = Config::load(&config_path).unwrap();
+    assert_eq!(config.games.len(), 2);
+    assert!(!config.game_files.contains_key("touhou"));
+    assert!(config.game_files.contains_key("cave_story"));
+
+    let conflicts: Vec<_> = check_file(&config_path)
+        .into_iter()
+        .filter(|diagnostic| diagnostic.message.contains("also defined"))
+        .collect();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path, games_dir.join("touhou.toml"));
+
+    // Files that aren't named after a valid game ID aren't loaded
+    fs::write(games_dir.join("cave story.toml"), game).unwrap();
+    let mut config: Config = toml::from_str(&inline).unwrap();
+    let skipped = config.load_games_dir(&config_path).unwrap();
+    assert_eq!(skipped.len(), 2);
+    assert!(skipped.iter().any(|skipped| skipped.contains("cave story.toml")));
+    assert!(!config.games.contains_key("cave story"));
+    assert!(check_file(&config_path)
+        .iter()
+        .any(|diagnostic| diagnostic.path == games_dir.join("cave story.toml")));
+    fs::remove_file(games_dir.join("cave story.toml")).unwrap();
+
+    // games_dir is relative to the config file, not the working directory
+    let relative = inline.replace(&format!("{:?}", games_dir), "\"games.d\"");
+    fs::write(&config_path, &relative).unwrap();
+    let config = Config::load(&config_path).unwrap();
+    assert!(config.game_files.contains_key("cave_story"));
+    assert_eq!(config.static_dir_path(), dir.join("static"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_save_and_remove_games() {
+    use config::is_valid_game_id;
+    use std::env;
+    use std::fs;
+
+    let dir = env::temp_dir().join("arclightning_test_save_games");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("games.d")).unwrap();
+    let config_path = dir.join("server_config.toml");
+    fs::write(
+        &config_path,
+        "config_version = 2\nlisten_port = 3000\nstatic_dir = \"static\"\n[games]\n",
+    ).unwrap();
+
+    let game = Game {
+        name: "Cave Story".to_owned(),
+        exe_path: PathBuf::from("cave/Doukutsu.exe"),
+        ..Default::default()
+    };
+
+    let mut config = Config::load(&config_path).unwrap();
+    config.save_game(&config_path, "cave_story", game.clone()).unwrap();
+    assert_eq!(Config::load(&config_path).unwrap().games["cave_story"], game);
+    // Nothing is left behind by the atomic write
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = fs::metadata(&config_path).unwrap().permissions();
+        permissions.set_mode(0o640);
+        fs::set_permissions(&config_path, permissions).unwrap();
+        config.save_game(&config_path, "cave_story", game.clone()).unwrap();
+        let mode = fs::metadata(&config_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    let bad_game = Game {
+        exe_args: vec!["{nope}".to_owned()],
+        ..game.clone()
+    };
+    assert!(config.save_game(&config_path, "bad", bad_game).is_err());
+    assert!(!Config::load(&config_path).unwrap().games.contains_key("bad"));
+
+    assert_eq!(config.remove_game(&config_path, "cave_story").unwrap(), Some(game.clone()));
+    assert_eq!(config.remove_game(&config_path, "cave_story").unwrap(), None);
+    assert!(Config::load(&config_path).unwrap().games.is_empty());
+
+    // With a games_dir, new games get a file of their own
+    config.games_dir = Some(dir.join("games.d"));
+    config.write_to_path(&config_path).unwrap();
+    config.save_game(&config_path, "cave_story", game.clone()).unwrap();
+    assert!(dir.join("games.d").join("cave_story.toml").is_file());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join("games.d").join("cave_story.toml");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    assert!(!fs::read_to_string(&config_path).unwrap().contains("Cave Story"));
+    config.remove_game(&config_path, "cave_story").unwrap();
+    assert!(!dir.join("games.d").join("cave_story.toml").exists());
+
+    assert!(is_valid_game_id("touhou_6-eosd"));
+    assert!(!is_valid_game_id("../server_config"));
+    assert!(!is_valid_game_id(""));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_config_edits_keep_formatting() {
+    use document::edit_source;
+
+    let source = "# Cabinet in the lounge\n\
+                  listen_port = 3000\n\
+                  static_dir = '..\\arclightning_frontend' # literal string\n\
+                  \n\
+                  [auth]\n\
+                  password_hash = \"old\"\n\
+                  \n\
+                  [games.touhou]\n\
+                  name = \"Touhou 14\"\n\
+                  description = \"\"\n\
+                  tags = [\"bullet hell\"]\n\
+                  thumbnail_path = 'images\\touhou.png'\n\
+                  exe_path = 'C:\\Games\\th14e.exe'\n\
+                  exe_args = []\n\
+                  \n\
+                  # Keep this one last\n\
+                  [games.bbtag]\n\
+                  name = \"BlazBlue\"\n\
+                  description = \"\"\n\
+                  tags = []\n\
+                  thumbnail_path = 'images\\bbtag.jpg'\n\
+                  exe_path = 'D:\\BBTAG.exe'\n\
+                  exe_args = []\n";
+
+    let mut config: Config = toml::from_str(source).unwrap();
+    config.auth.password_hash = Some("new".to_owned());
+    config.games.get_mut("touhou").unwrap().tags.push("2d".to_owned());
+    config.games.get_mut("bbtag").unwrap().working_dir = Some(PathBuf::from("D:\\"));
+    config.games.insert(
+        "melty".to_owned(),
+        Game {
+            name: "Melty Blood".to_owned(),
+            ..Default::default()
+        },
+    );
+
+    let edited = edit_source(source, &config).unwrap().unwrap();
+    let expected = source
+        .replace("password_hash = \"old\"", "password_hash = \"new\"")
+        .replace("[\"bullet hell\"]", "[\"bullet hell\", \"2d\"]")
+        + "working_dir = 'D:\\'\n";
+    assert!(edited.starts_with(&expected));
+    assert!(edited[expected.len()..].contains("[games.melty]"));
+    assert_eq!(toml::from_str::<Config>(&edited).unwrap(), config);
+
+    config.games.remove("touhou");
+    let edited = edit_source(&edited, &config).unwrap().unwrap();
+    assert!(!edited.contains("Touhou"));
+    assert!(edited.contains("# Keep this one last\n[games.bbtag]"));
+
+    // Documents that can't be edited are left for a full rewrite
+    assert_eq!(edit_source("listen_port = ", &config).unwrap(), None);
+}
+
+#[test]
+fn test_config_migration() {
+    use migrate;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    let dir = env::temp_dir().join("arclightning_test_migration");
+    let games_dir = dir.join("games.d");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&games_dir).unwrap();
+
+    let game = "name = \"Cave Story\"\ndescription = \"\"\n\
+                genres = [\"platformer\"] # keep me\n\
+                thumbnail_path = \"cave.png\"\nexe_path = \"Doukutsu.exe\"\nexe_args = []\n";
+    fs::write(games_dir.join("cave_story.toml"), game).unwrap();
+
+    let config_path = dir.join("server_config.toml");
+    let source = format!(
+        "# Cabinet in the lounge\n\
+         listen_port = 3000\n\
+         static_dir = \"static\"\n\
+         password = \"hunter2\"\n\
+         games_dir = {:?}\n\
+         \n\
+         [games.touhou]\n{}",
+        games_dir,
+        game.replace("Cave Story", "Touhou")
+    );
+    fs::write(&config_path, &source).unwrap();
+
+    // A dry run only describes the upgrade
+    let upgrade = migrate::plan(&config_path).unwrap().unwrap();
+    assert_eq!(upgrade.from, 1);
+    assert_eq!(upgrade.files.len(), 2);
+    let diff = upgrade.diff();
+    assert!(diff.contains("\n-password = \"hunter2\"\n"));
+    assert!(diff.contains("\n+[auth]\n+password_hash = \"hunter2\"\n"));
+    assert!(diff.contains("\n+tags = [\"platformer\"] # keep me\n"));
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), source);
+    let path = Path::new("a.toml");
+    assert_eq!(
+        migrate::diff(path, "a\nb\nc\n", "a\nB\nc\n"),
+        "--- a.toml\n+++ a.toml\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+    );
+    assert!(migrate::diff(path, "", "a\n").contains("@@ -0,0 +1,1 @@\n+a\n"));
+
+    // An [auth] table that is already there is merged into
+    let v1 = "password = \"hunter2\"\n[auth]\nsecure_cookie = true\n";
+    let mut document: toml_edit::Document = v1.parse().unwrap();
+    migrate::migrate_document(&mut document).unwrap();
+    let migrated = document.to_string();
+    assert_eq!(migrated.matches("[auth]").count(), 1);
+    assert!(migrated.contains("secure_cookie = true"));
+    assert!(migrated.contains("password_hash = \"hunter2\""));
+
+    let upgraded = &upgrade.files[0].new;
+    assert!(upgraded.starts_with("# Cabinet in the lounge\nconfig_version = 2\nlisten_port = 3000\n"));
+    assert!(upgraded.contains("\nname = \"Touhou\"\ndescription = \"\"\ntags = "));
+
+    // Loading an old config upgrades it on disk, keeping backups
+    let config = Config::load(&config_path).unwrap();
+    assert_eq!(config.config_version, CONFIG_VERSION);
+    assert_eq!(config.auth.password_hash, Some("hunter2".to_owned()));
+    assert_eq!(config.games["touhou"].tags, vec!["platformer".to_owned()]);
+    assert_eq!(config.games["cave_story"].tags, vec!["platformer".to_owned()]);
+    assert_eq!(
+        fs::read_to_string(dir.join("server_config.toml.v1.bak")).unwrap(),
+        source
+    );
+    assert!(games_dir.join("cave_story.toml.v1.bak").is_file());
+    assert_eq!(migrate::plan(&config_path).unwrap(), None);
+
+    fs::write(&config_path, "config_version = 99\n").unwrap();
+    assert!(Config::load(&config_path).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_options_precedence() {
+    use options::Options;
+
+    let args = |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+    let env = |name: &str| match name {
+        "ARCLIGHTNING_PORT" => Some("4000".to_owned()),
+        "ARCLIGHTNING_BIND" => Some("0.0.0.0".to_owned()),
+        _ => None,
+    };
+
+    let mut config: Config = toml::from_str(
+        "listen_port = 3000\nstatic_dir = \"static\"\n[games]\n",
+    ).unwrap();
+    assert_eq!(config.listen_addr().to_string(), "127.0.0.1:3000");
+
+    // The environment overrides the config file
+    let options = Options::parse(args(&[]), &env).unwrap();
+    assert_eq!(options.config_path, PathBuf::from("server_config.toml"));
+    options.apply(&mut config);
+    assert_eq!(config.listen_addr().to_string(), "0.0.0.0:4000");
+
+    // The command line overrides the environment
+    let options = Options::parse(
+        args(&["--bind", "[::1]", "--port=5000", "--static-dir", "dist", "--config", "a.toml"]),
+        &env,
+    ).unwrap();
+    options.apply(&mut config);
+    assert_eq!(config.listen_addr().to_string(), "[::1]:5000");
+    assert_eq!(config.static_dir_path(), ::std::env::current_dir().unwrap().join("dist"));
+    assert_eq!(options.config_path, PathBuf::from("a.toml"));
+
+    assert!(Options::parse(args(&["--port", "0"]), &env).is_err());
+    assert!(Options::parse(args(&["--bind"]), &env).is_err());
+    assert!(Options::parse(args(&["--verbose"]), &env).is_err());
+    assert!(Options::parse(args(&[]), |_| Some("nope".to_owned())).is_err());
+    // A flag replaces a bad variable without it being looked at
+    let bad_port = |name: &str| match name {
+        "ARCLIGHTNING_PORT" => Some("nope".to_owned()),
+        _ => None,
+    };
+    assert_eq!(Options::parse(args(&["--port", "5000"]), &bad_port).unwrap().port, Some(5000));
+
+    // Commands writing the config only take its path
+    let options = Options::parse_config_only(args(&["--config", "a.toml"]), &env).unwrap();
+    assert_eq!(options.config_path, PathBuf::from("a.toml"));
+    assert_eq!((options.bind, options.port), (None, None));
+    assert!(Options::parse_config_only(args(&["--port", "5000"]), &env).is_err());
+    assert!(Options::parse_config_only(args(&["--static-dir=dist"]), &env).is_err());
+}
+
+#[test]
+fn test_password_hash_format() {
+    assert!(password::is_valid_hash(
+        "$2y$04$RNzRQu2G0CeZACdaT/XeeeWp/c2nSZwnC15C9sM3MULDuT0NHJ1o6"
+    ));
+    assert!(!password::is_valid_hash("hunter2"));
+    assert!(!password::is_valid_hash(
+        "$2y$40$RNzRQu2G0CeZACdaT/XeeeWp/c2nSZwnC15C9sM3MULDuT0NHJ1o6"
+    ));
+    assert!(password::is_valid_hash(
+        "$argon2id$v=19$m=19456,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$7hAfRq0e4nh3EMHoQHDWYIQXCyHyCF/nwF2wc1Vhh5E"
+    ));
+    assert!(!password::is_valid_hash(
+        "$argon2id$v=19$m=19456,t=0,p=1$c29tZXNhbHRzb21lc2FsdA$7hAfRq0e4nh3EMHoQHDWYIQXCyHyCF/nwF2wc1Vhh5E"
+    ));
+    assert!(!password::is_valid_hash("$argon2i$v=19$m=19456,t=2,p=1$c29tZXNhbHQ$aGFzaA"));
+}
+
+#[test]
+fn test_steam_import() {
+    use config::suggested_game_id;
+    use launcher::build_launch;
+    use launcher::{LaunchContext, LaunchDefaults};
+    use std::env;
+    use std::fs;
+    use steam;
+    use validate::check_game;
+
+    let dir = env::temp_dir().join("arclightning_test_steam");
+    let _ = fs::remove_dir_all(&dir);
+    let steam_dir = dir.join("Steam");
+    let library = dir.join("SteamLibrary");
+    fs::create_dir_all(steam_dir.join("steamapps")).unwrap();
+    fs::create_dir_all(library.join("steamapps").join("common").join("BBTAG")).unwrap();
+
+    fs::write(
+        steam_dir.join("steamapps").join("libraryfolders.vdf"),
+        format!(
+            "\"libraryfolders\"\n{{\n\t\"contentstatsid\"\t\t\"123\"\n\
+             \t\"0\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n\
+             \t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t\t\"apps\" {{ \"702890\" \"123\" }}\n\t}}\n}}\n",
+            steam_dir.display().to_string().replace('\\', "\\\\"),
+            library.display().to_string().replace('\\', "\\\\")
+        ),
+    ).unwrap();
+    fs::write(
+        library.join("steamapps").join("appmanifest_702890.acf"),
+        "\"AppState\"\n{\n\t\"appid\"\t\t\"702890\"\n\t\"name\"\t\t\"BlazBlue: Cross Tag Battle\"\n\
+         \t\"installdir\"\t\t\"BBTAG\"\n\t// comment\n\t\"UserConfig\" { \"language\" \"english\" }\n}\n",
+    ).unwrap();
+    fs::write(
+        library.join("steamapps").join("appmanifest_228980.acf"),
+        "\"AppState\" { \"appid\" \"228980\" \"name\" \"Steamworks Common Redistributables\" \
+         \"installdir\" \"Steamworks Shared\" }",
+    ).unwrap();
+    fs::write(
+        steam_dir.join("steamapps").join("appmanifest_1.acf"),
+        "\"AppState\" { \"appid\" ",
+    ).unwrap();
+    fs::write(
+        steam_dir.join("steamapps").join("appmanifest_1000.acf"),
+        "\"AppState\" { \"appid\" \"1000\" \"name\" \"東方\" \"installdir\" \"Touhou\" }",
+    ).unwrap();
+
+    assert_eq!(
+        steam::library_folders(&steam_dir).unwrap(),
+        vec![steam_dir.clone(), library.clone()]
+    );
+    let found = steam::find_games(&steam_dir).unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].id, "blazblue_cross_tag_battle");
+    assert_eq!(found[1].id, "steam_1000");
+    assert_eq!(found[0].app_id, 702_890);
+    assert_eq!(found[0].game.exe_path, PathBuf::from("steam://rungameid/702890"));
+    assert_eq!(found[0].install_dir, library.join("steamapps").join("common").join("BBTAG"));
+    assert!(steam::find_games(&dir).is_err());
+
+    // Steam games aren't executables on disk, and exit right after launching
+    let problems = check_game(&found[0].game, &dir);
+    assert!(!problems.iter().any(|problem| problem.starts_with("exe_path")));
+    let launch = build_launch(&found[0].game, &LaunchContext::default(), &LaunchDefaults::default())
+        .unwrap();
+    assert!(launch.detached);
+
+    let config_path = dir.join("server_config.toml");
+    fs::write(
+        &config_path,
+        "config_version = 2\nlisten_port = 3000\nstatic_dir = \"static\"\n[games]\n",
+    ).unwrap();
+    let mut config = Config::load(&config_path).unwrap();
+    let report = config
+        .merge_launchable_games(&config_path, vec![(found[0].id.clone(), found[0].game.clone())])
+        .unwrap();
+    assert_eq!(report.added, vec!["blazblue_cross_tag_battle".to_owned()]);
+    assert_eq!(
+        Config::load(&config_path).unwrap().games["blazblue_cross_tag_battle"],
+        found[0].game
+    );
+
+    let renamed = vec![("bbtag".to_owned(), found[0].game.clone())];
+    let report = config.merge_games(&config_path, renamed).unwrap();
+    assert!(report.added.is_empty());
+    assert_eq!(report.skipped["bbtag"], "already imported as blazblue_cross_tag_battle");
+
+    // Games that don't validate are reported rather than failing the rest
+    let mut broken = found[0].game.clone();
+    broken.exe_path = PathBuf::from("steam://rungameid/2");
+    broken.exe_args = vec!["{unknown}".to_owned()];
+    let mut valid = found[0].game.clone();
+    valid.exe_path = PathBuf::from("steam://rungameid/3");
+    let games = vec![("broken".to_owned(), broken), ("valid".to_owned(), valid)];
+    let report = config.merge_games(&config_path, games).unwrap();
+    assert_eq!(report.added, vec!["valid".to_owned()]);
+    assert!(report.skipped["broken"].contains("exe_args"));
+
+    // Games that couldn't be launched aren't imported
+    let mut uninstalled = found[0].game.clone();
+    uninstalled.exe_path = PathBuf::from("steam://rungameid/1");
+    uninstalled.working_dir = Some(dir.join("missing"));
+    let report = config
+        .merge_launchable_games(&config_path, vec![("uninstalled".to_owned(), uninstalled)])
+        .unwrap();
+    assert!(report.skipped["uninstalled"].starts_with("working_dir"));
+
+    assert_eq!(suggested_game_id("GUILTY GEAR Xrd -REV 2-"), "guilty_gear_xrd_rev_2");
+    assert!(steam::parse("\"a\" { \"b\" \"c\"").is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_frontend_xml() {
+    use frontends::{self, Format, Unmapped};
+    use std::env;
+    use std::fs;
+
+    let dir = env::temp_dir().join("arclightning_test_frontends");
+    let _ = fs::remove_dir_all(&dir);
+    let platforms = dir.join("LaunchBox").join("Data").join("Platforms");
+    fs::create_dir_all(&platforms).unwrap();
+
+    let gamelist = dir.join("gamelist.xml");
+    fs::write(
+        &gamelist,
+        "<?xml version=\"1.0\"?>\n<gameList>\n\
+         \t<game id=\"42\">\n\t\t<path>./mslug.zip</path>\n\t\t<name>Metal Slug</name>\n\
+         \t\t<desc>Run and gun</desc>\n\t\t<image>./images/mslug.png</image>\n\
+         \t\t<genre>Shooter / Run &amp; Gun</genre>\n\t\t<players>1-2</players>\n\t\t<rating></rating>\n\
+         \t</game>\n\t<game><path>./mslug.zip</path><name>Metal Slug</name></game>\n\
+         \t<folder><name>Hacks</name></folder>\n</gameList>\n",
+    ).unwrap();
+    let import = frontends::import(&gamelist, None, &dir).unwrap();
+    let ids: Vec<&str> = import.games.iter().map(|&(ref id, _)| id.as_str()).collect();
+    assert_eq!(ids, vec!["metal_slug", "metal_slug_2"]);
+    let game = &import.games[0].1;
+    assert_eq!(game.exe_path, dir.join("mslug.zip"));
+    assert_eq!(game.thumbnail_path, PathBuf::from("images/mslug.png"));
+    assert_eq!(game.tags, vec!["Shooter".to_owned(), "Run & Gun".to_owned()]);
+    assert_eq!(game.description, "Run and gun");
+    assert_eq!((game.min_players, game.max_players), (Some(1), Some(2)));
+    let unmapped = |game: Option<&str>, field: &str, value: &str| Unmapped {
+        game: game.map(str::to_owned),
+        field: field.to_owned(),
+        value: value.to_owned(),
+    };
+    assert_eq!(
+        import.unmapped,
+        vec![
+            unmapped(Some("metal_slug"), "@id", "42"),
+            unmapped(None, "folder", "Hacks"),
+        ]
+    );
+    // Images outside of static_dir can't be thumbnails
+    let import = frontends::import(&gamelist, None, &dir.join("static")).unwrap();
+    assert_eq!(import.games[0].1.thumbnail_path, PathBuf::new());
+    assert!(import.unmapped.contains(&unmapped(Some("metal_slug"), "image", "./images/mslug.png")));
+
+    let platform = platforms.join("Arcade.xml");
+    fs::write(
+        &platform,
+        "<?xml version=\"1.0\" standalone=\"yes\"?>\n<LaunchBox>\n  <Game>\n\
+         \x20   <ApplicationPath>Games\\BBTAG.exe</ApplicationPath>\n\
+         \x20   <CommandLine>-windowed \"-config file.ini\" {json}</CommandLine>\n\
+         \x20   <Title>BlazBlue: Cross Tag Battle</Title>\n    <Genre>Fighting; Anime</Genre>\n\
+         \x20   <Developer>Arc System Works</Developer>\n  </Game>\n</LaunchBox>\n",
+    ).unwrap();
+    let import = frontends::import(&platform, Some(Format::LaunchBox), &dir).unwrap();
+    let (ref id, ref game) = import.games[0];
+    assert_eq!(id, "blazblue_cross_tag_battle");
+    assert_eq!(game.exe_path, dir.join("LaunchBox").join("Games\\BBTAG.exe"));
+    assert_eq!(
+        game.exe_args,
+        vec!["-windowed".to_owned(), "-config file.ini".to_owned(), "{{json}}".to_owned()]
+    );
+    assert_eq!(game.tags, vec!["Fighting".to_owned(), "Anime".to_owned()]);
+    assert_eq!(game.developer, Some("Arc System Works".to_owned()));
+    assert!(import.unmapped.is_empty());
+    assert!(frontends::import(&platform, Some(Format::EmulationStation), &dir).is_err());
+
+    // Exports read back as the same games, less what the format can't hold
+    let mut games = HashMap::new();
+    games.insert(id.clone(), game.clone());
+    let exported = platforms.join("Exported.xml");
+    let (xml, unmapped) = frontends::export(Format::LaunchBox, &games, &exported, &dir).unwrap();
+    fs::write(&exported, &xml).unwrap();
+    assert!(xml.contains("\"-config file.ini\" {json}<"));
+    assert_eq!(frontends::import(&exported, None, &dir).unwrap().games[0].1, *game);
+    assert!(unmapped.is_empty());
+
+    games.get_mut(id).unwrap().thumbnail_path = PathBuf::from("images/bbtag.png");
+    let exported = dir.join("exported").join("gamelist.xml");
+    let (xml, unmapped) = frontends::export(Format::EmulationStation, &games, &exported, &dir).unwrap();
+    assert!(xml.contains("<genre>Fighting, Anime</genre>"));
+    assert!(xml.contains(&format!("<image>{}</image>", dir.join("images/bbtag.png").display())));
+    assert_eq!(unmapped[0].field, "exe_args");
+    let (xml, _) = frontends::export(Format::EmulationStation, &games, &gamelist, &dir).unwrap();
+    assert!(xml.contains("<image>./images/bbtag.png</image>"));
+    fs::write(&gamelist, &xml).unwrap();
+    let import = frontends::import(&gamelist, None, &dir).unwrap();
+    assert_eq!(import.games[0].1.thumbnail_path, PathBuf::from("images/bbtag.png"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_catalog_query() {
+    use catalog::{Query, SortKey};
+    use config::{Input, PlayMode};
+
+    let mut games = HashMap::new();
+    games.insert(
+        "bbtag".to_owned(),
+        Game {
+            name: "BlazBlue Cross Tag Battle".to_owned(),
+            tags: vec!["Fighter".to_owned()],
+            max_players: Some(2),
+            play_modes: vec![PlayMode::Versus],
+            inputs: vec![Input::Stick, Input::Pad],
+            release_year: Some(2018),
+            ..Default::default()
+        },
+    );
+    games.insert(
+        "mbaa".to_owned(),
+        Game {
+            name: "Melty Blood".to_owned(),
+            tags: vec!["fighter".to_owned()],
+            max_players: Some(2),
+            release_year: Some(2008),
+            sort_order: Some(1),
+            ..Default::default()
+        },
+    );
+    games.insert(
+        "touhou".to_owned(),
+        Game {
+            name: "Touhou".to_owned(),
+            tags: vec!["bullet hell".to_owned()],
+            ..Default::default()
+        },
+    );
+    let ids = |query: &Query| -> Vec<String> {
+        query
+            .apply(&games)
+            .into_iter()
+            .map(|(id, _)| id.clone())
+            .collect()
+    };
+    let parse = |query: &str| {
+        let params: HashMap<&str, &str> = query
+            .split('&')
+            .map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                (parts.next().unwrap(), parts.next().unwrap())
+            })
+            .collect();
+        Query::parse(|key| params.get(key).map(|value| value.to_string()))
+    };
+
+    // sort_order first, then by name
+    assert_eq!(ids(&Query::default()), vec!["mbaa", "bbtag", "touhou"]);
+    assert_eq!(ids(&parse("genre=FIGHTER&sort=year").unwrap()), vec!["mbaa", "bbtag"]);
+    assert_eq!(ids(&parse("players=2&sort=-year").unwrap()), vec!["bbtag", "mbaa"]);
+    assert_eq!(ids(&parse("players=1&sort=-name").unwrap()), vec!["touhou", "mbaa", "bbtag"]);
+    assert_eq!(ids(&parse("mode=versus&input=stick").unwrap()), vec!["bbtag"]);
+    assert!(ids(&parse("input=light_gun").unwrap()).is_empty());
+
+    assert_eq!(parse("sort=-players").unwrap().sort, Some(SortKey::Players));
+    assert!(parse("players=two").is_err());
+    assert!(parse("input=wheel").is_err());
+    assert!(parse("sort=random").is_err());
+
+    let mut game = games["touhou"].clone();
+    game.min_players = Some(3);
+    game.max_players = Some(2);
+    assert!(game.validate("touhou").is_err());
+}
+
+#[test]
+fn test_sessions() {
+    use sessions::{SessionPolicy, Sessions};
+
+    let policy = SessionPolicy {
+        idle_secs: 60,
+        max_age_secs: 300,
+    };
+    let sessions = Sessions::new();
+    let laptop = sessions.create(&policy, Some("laptop".to_owned()), 1000).unwrap();
+    let phone = sessions.create(&policy, None, 1010).unwrap();
+    assert_ne!(laptop, phone);
+
+    // A second login leaves the first one logged in
+    assert!(sessions.check(&laptop, &policy, 1050).unwrap());
+    assert!(sessions.check(&phone, &policy, 1050).unwrap());
+    assert!(!sessions.check("not a token", &policy, 1050).unwrap());
+
+    // Idle sessions expire, and using a session keeps it alive until its
+    // maximum age
+    assert!(!sessions.check(&phone, &policy, 1111).unwrap());
+    for now in (1100..1300).step_by(50) {
+        assert!(sessions.check(&laptop, &policy, now).unwrap());
+    }
+    assert!(!sessions.check(&laptop, &policy, 1300).unwrap());
+
+    let listed = |sessions: &Sessions, now| sessions.list(&policy, now).unwrap();
+    let a = sessions.create(&policy, None, 2000).unwrap();
+    let b = sessions.create(&policy, Some("cabinet".to_owned()), 2030).unwrap();
+    assert_eq!(listed(&sessions, 2040).len(), 2);
+    assert_eq!(listed(&sessions, 2040)[1].client, Some("cabinet".to_owned()));
+    assert_eq!(sessions.purge(&policy, 2070).unwrap(), 1);
+    assert!(!sessions.check(&a, &policy, 2070).unwrap());
+
+    // Logging out and revoking both end a session
+    let b_id = sessions.id_of(&b).unwrap().unwrap();
+    assert!(sessions.revoke(&b_id).unwrap());
+    assert!(!sessions.revoke(&b_id).unwrap());
+    assert!(!sessions.check(&b, &policy, 2070).unwrap());
+    let c = sessions.create(&policy, None, 2070).unwrap();
+    assert!(sessions.remove(&c).unwrap());
+    assert!(!sessions.remove(&c).unwrap());
+    assert!(listed(&sessions, 2070).is_empty());
+
+    // Huge limits in [auth] saturate instead of overflowing
+    let auth = ::config::Auth {
+        session_idle_minutes: u64::max_value(),
+        session_max_hours: u64::max_value() / 60,
+        lockout_minutes: u64::max_value(),
+        ..Default::default()
+    };
+    assert_eq!(auth.session_policy().idle_secs, u64::max_value());
+    assert_eq!(auth.session_policy().max_age_secs, u64::max_value());
+    assert_eq!(auth.throttle_policy().lockout_secs, u64::max_value());
+}
+
+#[test]
+fn test_session_cookies() {
+    use sessions::{
+        bearer_token, cookie_value, expired_cookie, session_cookie, SessionPolicy, SESSION_COOKIE,
+        WEB_SESSION_COOKIE,
+    };
+
+    let policy = SessionPolicy {
+        idle_secs: 60,
+        max_age_secs: 43200,
+    };
+    assert_eq!(
+        session_cookie(SESSION_COOKIE, "abc", &policy, false),
+        "arclightning_session=abc; HttpOnly; SameSite=Strict; Path=/; Max-Age=43200"
+    );
+    assert!(session_cookie(SESSION_COOKIE, "abc", &policy, true).ends_with("; Secure"));
+    assert!(session_cookie(WEB_SESSION_COOKIE, "abc", &policy, false)
+        .starts_with("arclightning_web_session=abc; "));
+    assert!(expired_cookie(SESSION_COOKIE, false).starts_with("arclightning_session=; "));
+    assert!(expired_cookie(SESSION_COOKIE, false).contains("Max-Age=0"));
+
+    let header = "theme=dark; arclightning_session=abc=; other=\"x\"";
+    assert_eq!(cookie_value(header, "arclightning_session"), Some("abc="));
+    assert_eq!(cookie_value(header, "other"), Some("x"));
+    assert_eq!(cookie_value(header, "session"), None);
+    assert_eq!(cookie_value("arclightning_session", "arclightning_session"), None);
+
+    assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+    assert_eq!(bearer_token("bearer  abc "), Some("abc"));
+    assert_eq!(bearer_token("Basic abc"), None);
+    assert_eq!(bearer_token("Bearer"), None);
+}
+
+#[test]
+fn test_login_throttle() {
+    use std::env;
+    use std::fs;
+    use throttle::{AuditRecord, Throttle, ThrottlePolicy};
+
+    let audit_path = env::temp_dir().join("arclightning_test_login_audit.jsonl");
+    let _ = fs::remove_file(&audit_path);
+    let policy = ThrottlePolicy {
+        free_attempts: 2,
+        base_delay_secs: 10,
+        max_delay_secs: 40,
+        lockout_after: 6,
+        lockout_secs: 600,
+        global_failures_per_minute: 100,
+    };
+    let throttle = Throttle::new().with_audit_log(audit_path.clone());
+    let wait = |client, now| throttle.retry_after(client, &policy, now).unwrap();
+    let fail = |client, now| {
+        assert!(throttle.begin_attempt(client, &policy, now).unwrap().is_ok());
+        throttle.failure(client, &policy, now).unwrap();
+    };
+
+    // The first failures are free, then the wait doubles up to the maximum
+    fail("10.0.0.1", 1000);
+    fail("10.0.0.1", 1000);
+    assert_eq!(wait("10.0.0.1", 1000), None);
+    fail("10.0.0.1", 1000);
+    assert_eq!(wait("10.0.0.1", 1004), Some(6));
+    assert_eq!(wait("10.0.0.1", 1010), None);
+    fail("10.0.0.1", 1010);
+    assert_eq!(wait("10.0.0.1", 1010), Some(20));
+    fail("10.0.0.1", 1030);
+    assert_eq!(wait("10.0.0.1", 1030), Some(40));
+
+    // Other clients aren't affected
+    assert_eq!(wait("10.0.0.2", 1030), None);
+
+    // Enough failures lock the client out, until it starts over
+    fail("10.0.0.1", 1070);
+    assert_eq!(wait("10.0.0.1", 1070), Some(600));
+    assert_eq!(wait("10.0.0.1", 1670), None);
+    fail("10.0.0.1", 2300);
+    assert_eq!(wait("10.0.0.1", 2300), None);
+
+    // A correct password clears a client's failures
+    fail("10.0.0.1", 2300);
+    let attempt = throttle.begin_attempt("10.0.0.1", &policy, 2300).unwrap().unwrap();
+    assert!(wait("10.0.0.1", 2300).is_some());
+    throttle.success("10.0.0.1", attempt).unwrap();
+    assert_eq!(wait("10.0.0.1", 2300), None);
+
+    // Attempts still being checked count as failures, so parallel guesses
+    // can't all get past the limit
+    let parallel = Throttle::new();
+    for _ in 0..3 {
+        assert!(parallel.begin_attempt("10.0.2.1", &policy, 3000).unwrap().is_ok());
+    }
+    assert_eq!(
+        parallel.begin_attempt("10.0.2.1", &policy, 3000).unwrap(),
+        Err(10)
+    );
+
+    // Failures from all clients together are capped per minute
+    let global = ThrottlePolicy {
+        global_failures_per_minute: 3,
+        ..policy
+    };
+    let spread = Throttle::new();
+    let attempts: Vec<_> = ["10.0.1.1", "10.0.1.2", "10.0.1.3"]
+        .iter()
+        .enumerate()
+        .map(|(i, client)| spread.begin_attempt(client, &global, 5000 + i as u64).unwrap())
+        .collect();
+    assert!(attempts.iter().all(Result::is_ok));
+    assert_eq!(
+        spread.retry_after("10.0.1.4", &global, 5010).unwrap(),
+        Some(50)
+    );
+    assert_eq!(spread.retry_after("10.0.1.4", &global, 5060).unwrap(), None);
+    // A correct password doesn't count against the cap
+    let attempt = attempts[2].unwrap();
+    spread.success("10.0.1.3", attempt).unwrap();
+    assert_eq!(spread.retry_after("10.0.1.4", &global, 5010).unwrap(), None);
+    // and only takes back its own attempt, not others begun at the same time
+    for client in &["10.0.1.4", "10.0.1.5"] {
+        assert!(spread.begin_attempt(client, &global, 5002).unwrap().is_ok());
+    }
+    spread.success("10.0.1.3", attempt).unwrap();
+    assert!(spread.retry_after("10.0.1.6", &global, 5010).unwrap().is_some());
+
+    let audit: Vec<AuditRecord> = fs::read_to_string(&audit_path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(audit[0].client, "10.0.0.1");
+    assert_eq!(audit[0].event, "failed");
+    assert_eq!(audit[2].retry_after, Some(10));
+    assert_eq!(audit[3].event, "throttled");
+    assert!(audit
+        .iter()
+        .any(|record| record.event == "locked_out" && record.failures == 6));
+    assert!(audit.iter().all(|record| record.client == "10.0.0.1"));
+    let _ = fs::remove_file(&audit_path);
+}
+
+#[test]
+fn test_accounts() {
+    use accounts::{validate_password, validate_username, Accounts};
+    use password::{HashPolicy, Scheme};
+    use sessions::{SessionPolicy, Sessions};
+    use std::env;
+    use std::fs;
+
+    assert!(validate_username("ryu_99").is_ok());
+    assert!(validate_username("k.o-fighter").is_ok());
+    assert!(validate_username("ab").is_err());
+    assert!(validate_username("_ken").is_err());
+    assert!(validate_username("chun li").is_err());
+    assert!(validate_username(&"x".repeat(33)).is_err());
+    assert!(validate_password("hadouken!", "ryu").is_ok());
+    assert!(validate_password("short", "ryu").is_err());
+    assert!(validate_password("Ryu_Hoshi", "ryu_hoshi").is_err());
+    assert!(validate_password(&"x".repeat(73), "ryu").is_err());
+
+    let path = env::temp_dir().join("arclightning_test_accounts.toml");
+    let _ = fs::remove_file(&path);
+    let policy = HashPolicy {
+        scheme: Scheme::Bcrypt,
+        bcrypt_cost: 4,
+        ..HashPolicy::default()
+    };
+
+    let accounts = Accounts::load(path.clone()).unwrap();
+    let ryu = accounts.register("Ryu", "hadouken!", &policy, 1000).unwrap();
+    assert_eq!(ryu.username, "Ryu");
+    assert_eq!(ryu.created, 1000);
+    assert!(password::is_valid_hash(&ryu.password_hash));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Usernames are unique ignoring case, and bad ones are rejected
+    let err = accounts.register("ryu", "shoryuken!", &policy, 1001).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    let err = accounts.register("ken", "short", &policy, 1001).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    // Sign in ignores the case of the username, but not the password
+    assert_eq!(accounts.sign_in("RYU", "hadouken!", &policy).unwrap(), Some(ryu.clone()));
+    assert_eq!(accounts.sign_in("Ryu", "HADOUKEN!", &policy).unwrap(), None);
+    assert_eq!(accounts.sign_in("ken", "hadouken!", &policy).unwrap(), None);
+
+    // Accounts are kept in the file, and weaker hashes are replaced on sign in
+    let reloaded = Accounts::load(path.clone()).unwrap();
+    assert_eq!(reloaded.get("ryu").unwrap(), Some(ryu.clone()));
+    let stronger = HashPolicy {
+        bcrypt_cost: 5,
+        ..policy
+    };
+    let rehashed = reloaded.sign_in("ryu", "hadouken!", &stronger).unwrap().unwrap();
+    assert_ne!(rehashed.password_hash, ryu.password_hash);
+    assert!(!password::needs_rehash(&rehashed.password_hash, &stronger));
+    let stored = Accounts::load(path.clone()).unwrap().get("Ryu").unwrap().unwrap();
+    assert_eq!(stored, rehashed);
+
+    // Web app sessions remember the account they are signed in to
+    let session_policy = SessionPolicy {
+        idle_secs: 60,
+        max_age_secs: 300,
+    };
+    let sessions = Sessions::new();
+    let token = sessions
+        .create_for_user(&session_policy, "Ryu", None, 1000)
+        .unwrap();
+    assert_eq!(sessions.user_of(&token).unwrap(), Some("Ryu".to_owned()));
+    let cabinet = sessions.create(&session_policy, None, 1000).unwrap();
+    assert_eq!(sessions.user_of(&cabinet).unwrap(), None);
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,295 @@
+// Slows down password guessing on /api/v1/check_password.
+//
+// Every attempt is counted as a failure from the moment it starts until
+// its password turns out to be right. Each client gets a few free failed
+// attempts. After that every failure
+// doubles how long it has to wait before trying again, until it is locked
+// out for a while. Failures from all clients together are also capped per
+// minute, so guessing from many addresses at once is slowed down too.
+// Failed and refused attempts are written to an audit log.
+
+use serde_json;
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::{self, ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+const GLOBAL_WINDOW_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThrottlePolicy {
+    // Failures a client can make before it has to wait
+    pub free_attempts: u32,
+    // The wait after the first failure past the free ones, doubled for
+    // every failure after that
+    pub base_delay_secs: u64,
+    pub max_delay_secs: u64,
+    // Failures after which a client is locked out
+    pub lockout_after: u32,
+    // How long a lockout lasts. A client that stops failing for this long
+    // starts over with free attempts.
+    pub lockout_secs: u64,
+    // Failures allowed per minute from all clients together
+    pub global_failures_per_minute: usize,
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self {
+        ThrottlePolicy {
+            free_attempts: 3,
+            base_delay_secs: 2,
+            max_delay_secs: 300,
+            lockout_after: 10,
+            lockout_secs: 15 * 60,
+            global_failures_per_minute: 30,
+        }
+    }
+}
+
+// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditRecord {
+    pub time: u64,
+    pub client: String,
+    // "failed", "locked_out" or "throttled"
+    pub event: String,
+    // Consecutive failures from this client so far
+    pub failures: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ClientState {
+    failures: u32,
+    last_failure: u64,
+    blocked_until: u64,
+}
+
+// A password attempt let through by begin_attempt. It counts as a failure
+// until it is passed to `success`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attempt(u64);
+
+#[derive(Debug, Default)]
+struct Inner {
+    clients: HashMap<String, ClientState>,
+    // Times of recent failures from any client, oldest first, with the
+    // attempt each one was counted for
+    recent_failures: VecDeque<(u64, Attempt)>,
+    next_attempt: u64,
+}
+
+// Shared by every clone of the router, like the sessions
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    inner: Arc<Mutex<Inner>>,
+    audit_log: Option<PathBuf>,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Throttle::default()
+    }
+
+    // Appends failed and refused attempts to `path`, one JSON object a line
+    pub fn with_audit_log(mut self, path: PathBuf) -> Self {
+        self.audit_log = Some(path);
+        self
+    }
+
+    fn lock(&self) -> Result<MutexGuard<Inner>, io::Error> {
+        self.inner.lock().map_err(|err| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("Failed to acquire mutex on login throttle: {}", err),
+            )
+        })
+    }
+
+    fn audit(&self, record: &AuditRecord) {
+        let path = match self.audit_log {
+            Some(ref path) => path,
+            None => return,
+        };
+        let written = serde_json::to_string(record)
+            .map_err(|err| io::Error::new(ErrorKind::Other, err))
+            .and_then(|json| {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", json)
+            });
+        // A full disk shouldn't lock everyone out
+        if let Err(err) = written {
+            println!("Failed to write to audit log {:?}: {}", path, err);
+        }
+    }
+
+    // How long `client` has to wait before trying a password, and its
+    // failures so far
+    fn blocked(
+        inner: &mut Inner,
+        client: &str,
+        policy: &ThrottlePolicy,
+        now: u64,
+    ) -> (Option<u64>, u32) {
+        while inner
+            .recent_failures
+            .front()
+            .map_or(false, |&(time, _)| time + GLOBAL_WINDOW_SECS <= now)
+        {
+            inner.recent_failures.pop_front();
+        }
+
+        let global = if inner.recent_failures.len() >= policy.global_failures_per_minute {
+            inner
+                .recent_failures
+                .front()
+                .map(|&(oldest, _)| oldest + GLOBAL_WINDOW_SECS - now)
+        } else {
+            None
+        };
+        let state = inner.clients.get(client).cloned().unwrap_or_default();
+        let own = if state.blocked_until > now {
+            Some(state.blocked_until - now)
+        } else {
+            None
+        };
+        (own.into_iter().chain(global).max(), state.failures)
+    }
+
+    // Counts a failure against `client` for a new attempt, extending its
+    // wait
+    fn count_failure(
+        inner: &mut Inner,
+        client: &str,
+        policy: &ThrottlePolicy,
+        now: u64,
+    ) -> Attempt {
+        let attempt = Attempt(inner.next_attempt);
+        inner.next_attempt += 1;
+        inner.recent_failures.push_back((now, attempt));
+        // Forget clients that have been quiet long enough to start over
+        inner.clients.retain(|_, state| {
+            state.blocked_until > now
+                || state.last_failure.saturating_add(policy.lockout_secs) > now
+        });
+
+        let state = inner
+            .clients
+            .entry(client.to_owned())
+            .or_insert_with(ClientState::default);
+        state.failures += 1;
+        state.last_failure = now;
+        if state.failures >= policy.lockout_after {
+            state.blocked_until = now.saturating_add(policy.lockout_secs);
+        } else if state.failures > policy.free_attempts {
+            let doublings = (state.failures - policy.free_attempts - 1).min(32);
+            let delay = policy
+                .base_delay_secs
+                .saturating_mul(1 << doublings)
+                .min(policy.max_delay_secs);
+            state.blocked_until = now.saturating_add(delay);
+        }
+        attempt
+    }
+
+    fn audit_throttled(&self, client: &str, failures: u32, retry_after: u64, now: u64) {
+        self.audit(&AuditRecord {
+            time: now,
+            client: client.to_owned(),
+            event: "throttled".to_owned(),
+            failures,
+            retry_after: Some(retry_after),
+        });
+    }
+
+    // How many seconds `client` has to wait before trying a password, or
+    // None if it may try now. Refusals are audited.
+    pub fn retry_after(
+        &self,
+        client: &str,
+        policy: &ThrottlePolicy,
+        now: u64,
+    ) -> Result<Option<u64>, io::Error> {
+        let (retry_after, failures) = Throttle::blocked(&mut *self.lock()?, client, policy, now);
+        if let Some(retry_after) = retry_after {
+            self.audit_throttled(client, failures, retry_after, now);
+        }
+        Ok(retry_after)
+    }
+
+    // Starts a password attempt from `client`. Returns Err with how many
+    // seconds it has to wait if it may not try now; refusals are audited.
+    // Otherwise the attempt counts as a failure until it is passed to
+    // `success`, so guesses sent in parallel can't all get past the limit
+    // before the first one is checked.
+    pub fn begin_attempt(
+        &self,
+        client: &str,
+        policy: &ThrottlePolicy,
+        now: u64,
+    ) -> Result<Result<Attempt, u64>, io::Error> {
+        let (retry_after, failures) = {
+            let mut inner = self.lock()?;
+            match Throttle::blocked(&mut inner, client, policy, now) {
+                (Some(retry_after), failures) => (retry_after, failures),
+                (None, _) => {
+                    return Ok(Ok(Throttle::count_failure(&mut inner, client, policy, now)))
+                }
+            }
+        };
+        self.audit_throttled(client, failures, retry_after, now);
+        Ok(Err(retry_after))
+    }
+
+    // Records a wrong password from `client`. An attempt from begin_attempt
+    // was already counted, so this only writes the audit log.
+    pub fn failure(
+        &self,
+        client: &str,
+        policy: &ThrottlePolicy,
+        now: u64,
+    ) -> Result<(), io::Error> {
+        let state = self
+            .lock()?
+            .clients
+            .get(client)
+            .cloned()
+            .unwrap_or_default();
+
+        let locked_out = state.failures >= policy.lockout_after;
+        if locked_out {
+            println!(
+                "Locked out {} for {} minutes after {} failed logins",
+                client,
+                policy.lockout_secs / 60,
+                state.failures
+            );
+        }
+        self.audit(&AuditRecord {
+            time: now,
+            client: client.to_owned(),
+            event: if locked_out { "locked_out" } else { "failed" }.to_owned(),
+            failures: state.failures,
+            retry_after: Some(state.blocked_until.saturating_sub(now)).filter(|&secs| secs > 0),
+        });
+        Ok(())
+    }
+
+    // Records a correct password from `client` for `attempt`, clearing its
+    // failures. Only that attempt's own failure is taken back from the
+    // global count.
+    pub fn success(&self, client: &str, attempt: Attempt) -> Result<(), io::Error> {
+        let mut inner = self.lock()?;
+        inner.clients.remove(client);
+        if let Some(index) = inner
+            .recent_failures
+            .iter()
+            .position(|&(_, counted)| counted == attempt)
+        {
+            inner.recent_failures.remove(index);
+        }
+        Ok(())
+    }
+}
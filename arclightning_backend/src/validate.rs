@@ -0,0 +1,123 @@
+use config::Game;
+use launcher::steam_app_id;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+fn is_executable_file(path: &Path) -> bool {
+    fs::metadata(path).map_or(false, |metadata| {
+        metadata.is_file() && is_executable(&metadata)
+    })
+}
+
+// A bare program name such as `retroarch`, which is looked up in PATH
+fn is_bare_name(path: &Path) -> bool {
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => !path.to_string_lossy().contains('/'),
+        _ => false,
+    }
+}
+
+// Where a bare program name is found when `game` is launched, searching
+// the PATH the game will get
+fn find_in_path(game: &Game) -> Option<PathBuf> {
+    let search_path = match game.env.get("PATH") {
+        Some(path) => OsString::from(path),
+        None if game.env_clear => return None,
+        None => env::var_os("PATH")?,
+    };
+    env::split_paths(&search_path)
+        .map(|dir| dir.join(&game.exe_path))
+        .flat_map(|path| {
+            // Windows also finds programs without their extension
+            let with_exe = if cfg!(windows) && path.extension().is_none() {
+                Some(path.with_extension("exe"))
+            } else {
+                None
+            };
+            Some(path).into_iter().chain(with_exe)
+        })
+        .find(|path| is_executable_file(path))
+}
+
+// Problems that would stop a game from launching or being shown properly
+pub fn check_game(game: &Game, static_dir: &Path) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // Steam URLs are opened by Steam rather than run directly
+    match steam_app_id(&game.exe_path) {
+        Some(app_id) if app_id.is_empty() || !app_id.chars().all(|c| c.is_ascii_digit()) => {
+            problems.push(format!("exe_path {:?} has an invalid Steam app ID", game.exe_path))
+        }
+        Some(_) => {}
+        None if is_bare_name(&game.exe_path) => {
+            if find_in_path(game).is_none() {
+                problems.push(format!("exe_path {:?} was not found in PATH", game.exe_path))
+            }
+        }
+        None => match fs::metadata(&game.exe_path) {
+            Ok(ref metadata) if !metadata.is_file() => {
+                problems.push(format!("exe_path {:?} is not a file", game.exe_path))
+            }
+            Ok(ref metadata) if !is_executable(metadata) => {
+                problems.push(format!("exe_path {:?} is not executable", game.exe_path))
+            }
+            Ok(_) => {}
+            Err(err) => problems.push(format!("exe_path {:?}: {}", game.exe_path, err)),
+        },
+    }
+
+    if let Some(ref working_dir) = game.working_dir {
+        if !working_dir.is_dir() {
+            problems.push(format!("working_dir {:?} is not a directory", working_dir));
+        }
+    }
+
+    // Thumbnails are optional, but served from static_dir, so they have to
+    // live inside it
+    if game.thumbnail_path == Path::new("") {
+        return problems;
+    }
+    let thumbnail = static_dir.join(&game.thumbnail_path);
+    match (thumbnail.canonicalize(), static_dir.canonicalize()) {
+        (Ok(ref thumbnail), Ok(ref static_dir)) if !thumbnail.starts_with(static_dir) => {
+            problems.push(format!(
+                "thumbnail_path {:?} is outside of static_dir",
+                game.thumbnail_path
+            ))
+        }
+        (Ok(ref thumbnail), Ok(_)) if !thumbnail.is_file() => problems.push(format!(
+            "thumbnail_path {:?} is not a file",
+            game.thumbnail_path
+        )),
+        (Ok(_), Ok(_)) => {}
+        (Err(err), _) => problems.push(format!("thumbnail_path {:?}: {}", game.thumbnail_path, err)),
+        (_, Err(err)) => problems.push(format!("static_dir {:?}: {}", static_dir, err)),
+    }
+
+    problems
+}
+
+// Problems for every game, keyed by game ID. Games without problems are
+// left out.
+pub fn check_games(games: &HashMap<String, Game>, static_dir: &Path) -> HashMap<String, Vec<String>> {
+    games
+        .iter()
+        .map(|(id, game)| (id.clone(), check_game(game, static_dir)))
+        .filter(|&(_, ref problems)| !problems.is_empty())
+        .collect()
+}